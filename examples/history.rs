@@ -0,0 +1,56 @@
+//! Example on how to read the game history of a deployed Rock Paper Scissors contract.
+//! This example queries the `GameCreated`, `Winner` and `Draw` events emitted by the contract
+//! and prints them in the order they happened, as a template for a leaderboard or match-history UI.
+//!
+//! It only reads logs, so it connects without a wallet and needs no private key.
+//!
+//! Run it with `cargo run --example history --features client`.
+
+use eyre::eyre;
+use rps_game::client::{read_only_instance, RpsBindingEvents};
+
+/// Stylus RPC endpoint URL environment variable name.
+const RPC_URL_ENV: &str = "RPC_URL";
+
+/// Deployed contract address environment variable name.
+const CONTRACT_ADDRESS_ENV: &str = "CONTRACT_ADDRESS";
+
+/// First block to query events from environment variable name. Defaults to 0.
+const FROM_BLOCK_ENV: &str = "FROM_BLOCK";
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let rpc_url =
+        std::env::var(RPC_URL_ENV).map_err(|_| eyre!("No {} env var set", RPC_URL_ENV))?;
+    let contract_address = std::env::var(CONTRACT_ADDRESS_ENV)
+        .map_err(|_| eyre!("No {} env var set", CONTRACT_ADDRESS_ENV))?;
+    let from_block = match std::env::var(FROM_BLOCK_ENV) {
+        Ok(block) => block
+            .parse::<u64>()
+            .map_err(|_| eyre!("Invalid {} env var: {}", FROM_BLOCK_ENV, block))?,
+        Err(_) => 0,
+    };
+
+    // Connect without a signer; reading logs needs no account
+    let rps = read_only_instance(rpc_url, contract_address.parse()?)?;
+
+    println!("Game history of {} from block {}", contract_address, from_block);
+
+    // Query all events from the contract, returned in the order they were emitted
+    let events = rps.events().from_block(from_block).query_with_meta().await?;
+    for (event, meta) in events {
+        match event {
+            RpsBindingEvents::GameCreatedFilter(game) => {
+                println!("[block {}] Game {} created by {:?} with a bet of {} wei", meta.block_number, game.game_id, game.creator, game.bet);
+            }
+            RpsBindingEvents::WinnerFilter(winner) => {
+                println!("[block {}] Game {}: {:?} won {} wei", meta.block_number, winner.game_id, winner.winner, winner.amount);
+            }
+            RpsBindingEvents::DrawFilter(draw) => {
+                println!("[block {}] Game {}: draw between {:?} and {:?}", meta.block_number, draw.game_id, draw.player_zero, draw.player_one);
+            }
+        }
+    }
+
+    Ok(())
+}
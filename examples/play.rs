@@ -1,19 +1,24 @@
-//! Example on how to interact with a deployed Rock Paper Scissors contract using the Stylus SDK.
-//! This example uses the Stylus SDK to instantiate the contract and interact with it.
-//! It attempts to initialize the contract, commit choices for two players, and distribute the winnings.
+//! Example on how to interact with a deployed Rock Paper Scissors contract.
+//! This example uses the `client` feature of this crate to instantiate the contract and interact with it.
+//! It attempts to initialize the contract, create a game, commit and reveal choices for two players, and distribute the winnings.
 //! The deployed contract is fully written in Rust and compiled to WASM.
+//!
+//! Run it with `cargo run --example play --features client`.
 
-use stylus_sdk::{
-    alloy_primitives::U256,
-    call, msg,
-    prelude::*,
-};
+use ethers::{contract::parse_log, core::rand, types::U256};
 use eyre::eyre;
-use std::str::FromStr;
+use rps_game::{
+    client::{GameCreatedFilter, StylusClient, RPS},
+    commitment::compute_commitment,
+};
 
 /// Your private key environment variable name.
 const PRIV_KEY_ENV: &str = "PRIV_KEY";
 
+/// The second player's private key environment variable name.
+/// The contract rejects one address playing both sides, so the second player needs its own account.
+const PLAYER_TWO_PRIV_KEY_ENV: &str = "PLAYER_TWO_PRIV_KEY";
+
 /// Stylus RPC endpoint URL environment variable name.
 const RPC_URL_ENV: &str = "RPC_URL";
 
@@ -24,6 +29,8 @@ const CONTRACT_ADDRESS_ENV: &str = "CONTRACT_ADDRESS";
 async fn main() -> eyre::Result<()> {
     let privkey =
         std::env::var(PRIV_KEY_ENV).map_err(|_| eyre!("No {} env var set", PRIV_KEY_ENV))?;
+    let player_two_privkey = std::env::var(PLAYER_TWO_PRIV_KEY_ENV)
+        .map_err(|_| eyre!("No {} env var set", PLAYER_TWO_PRIV_KEY_ENV))?;
     let rpc_url =
         std::env::var(RPC_URL_ENV).map_err(|_| eyre!("No {} env var set", RPC_URL_ENV))?;
     let contract_address = std::env::var(CONTRACT_ADDRESS_ENV)
@@ -32,36 +39,77 @@ async fn main() -> eyre::Result<()> {
     println!("RPC URL: {}", rpc_url);
     println!("Contract address: {}", contract_address);
 
-    // Create a new Stylus client
-    let client = StylusClient::new(rpc_url, privkey).await?;
+    // Create a Stylus client for each player
+    let client = StylusClient::new(rpc_url.clone(), privkey).await?;
+    let player_two_client = StylusClient::new(rpc_url, player_two_privkey).await?;
 
-    // Get the contract instance
+    // Get the contract instance for each player
     let rps = client.contract_instance::<RPS>(contract_address.parse()?);
+    let player_two_rps = player_two_client.contract_instance::<RPS>(contract_address.parse()?);
 
     println!("Connected to contract at address: {}", contract_address);
 
-    // Initialize the contract with a smaller bet amount
-    let bet_amount = U256::from(1_000_000_000_000_000u64); // 0.001 ETH
-    println!("Initializing the contract with a bet amount of {} wei", bet_amount);
-    let _ = rps.new(bet_amount).send().await?;
+    // Initialize the contract for single classic games
+    let min_bet = U256::from(1_000_000_000_000u64); // 0.000001 ETH
+    let max_bet = U256::from(1_000_000_000_000_000_000u64); // 1 ETH
+    let commit_span = U256::from(100); // Blocks the second player has to commit, and the players have to reveal
+    let rounds = U256::from(1);
+    let variant = U256::from(0); // Classic rules
+    let fee_bps = U256::from(0); // No house fee
+    let strict_bet = true; // Commits send exactly the bet
+    // The contract's `new` function is bound as `init`
+    println!("Initializing the contract");
+    let _ = rps.init(min_bet, max_bet, commit_span, rounds, variant, fee_bps, strict_bet).send().await?.await?;
     println!("Successfully initialized the contract");
 
-    // Player 1 commits their choice
-    let player1_choice = U256::from(1); // Rock
-    println!("Player 1 committing choice: {:?}", Choice::from(player1_choice));
-    let _ = rps.commit(player1_choice).value(bet_amount).send().await?;
-    println!("Player 1 successfully committed their choice");
+    // Create a game with a smaller bet amount, reading its id from the `GameCreated` event
+    let bet_amount = U256::from(1_000_000_000_000_000u64); // 0.001 ETH
+    println!("Creating a game with a bet amount of {} wei", bet_amount);
+    let receipt = rps
+        .create_game(bet_amount)
+        .send()
+        .await?
+        .await?
+        .ok_or(eyre!("No receipt for the create_game transaction"))?;
+    let game_id = receipt
+        .logs
+        .into_iter()
+        .find_map(|log| parse_log::<GameCreatedFilter>(log).ok())
+        .ok_or(eyre!("No GameCreated event in the create_game receipt"))?
+        .game_id;
+    println!("Successfully created game {}", game_id);
+
+    // Each player commits to a hidden choice, keeping the blinding factor to reveal it later
+    // The same helper is compiled into the contract, so both sides hash the identical preimage
+    let player1_choice = 1u8; // Rock
+    let player2_choice = 3u8; // Scissors
+    let player1_blinding = alloy_primitives::U256::from_be_bytes(rand::random::<[u8; 32]>());
+    let player2_blinding = alloy_primitives::U256::from_be_bytes(rand::random::<[u8; 32]>());
+    let player1_commitment = compute_commitment(player1_choice, player1_blinding, client.address().0.into());
+    let player2_commitment = compute_commitment(player2_choice, player2_blinding, player_two_client.address().0.into());
+
+    // The client binding uses ethers' `U256`, so convert through big-endian bytes
+    let to_ethers = |value: alloy_primitives::U256| U256::from_big_endian(&value.to_be_bytes::<32>());
+
+    println!("Player 1 committing {:#x} (Rock)", player1_commitment);
+    let _ = rps.commit(game_id, to_ethers(player1_commitment)).value(bet_amount).send().await?.await?;
+    println!("Player 1 successfully committed");
+
+    println!("Player 2 committing {:#x} (Scissors)", player2_commitment);
+    let _ = player_two_rps.commit(game_id, to_ethers(player2_commitment)).value(bet_amount).send().await?.await?;
+    println!("Player 2 successfully committed");
 
-    // Player 2 commits their choice
-    let player2_choice = U256::from(3); // Scissors
-    println!("Player 2 committing choice: {:?}", Choice::from(player2_choice));
-    let _ = rps.commit(player2_choice).value(bet_amount).send().await?;
-    println!("Player 2 successfully committed their choice");
+    // Once both players have committed, each reveals their choice and blinding factor
+    println!("Player 1 revealing choice: {} (Rock)", player1_choice);
+    let _ = rps.reveal(game_id, U256::from(player1_choice), to_ethers(player1_blinding)).send().await?.await?;
+    println!("Player 2 revealing choice: {} (Scissors)", player2_choice);
+    let _ = player_two_rps.reveal(game_id, U256::from(player2_choice), to_ethers(player2_blinding)).send().await?.await?;
+    println!("Both players successfully revealed their choices");
 
     // Distribute the winnings
     println!("Distributing the winnings");
-    let _ = rps.distribute().send().await?;
+    let _ = rps.distribute(game_id).send().await?.await?;
     println!("Successfully distributed the winnings");
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,92 @@
+//! Host-side client for interacting with a deployed Rock Paper Scissors contract.
+//! Enabled with the `client` feature; it is never compiled into the WASM contract.
+//!
+//! The client reads nothing from the environment itself. The `play` example expects:
+//! - `PRIV_KEY`: the private key of the account sending transactions.
+//! - `PLAYER_TWO_PRIV_KEY`: the private key of the second player, which must be a different account.
+//! - `RPC_URL`: the Stylus RPC endpoint URL.
+//! - `CONTRACT_ADDRESS`: the address of the deployed contract.
+//!
+//! The `history` example only reads logs, so it needs just `RPC_URL` and `CONTRACT_ADDRESS`.
+
+use ethers::{
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::Address,
+};
+use std::sync::Arc;
+
+// The functions are declared under the camelCase names the contract exports, as printed by `cargo stylus export-abi`;
+// abigen still generates snake_case methods, such as `create_game`, for them
+abigen!(
+    RpsBinding,
+    r#"[
+        function new(uint256 min_bet, uint256 max_bet, uint256 commit_span, uint256 rounds, uint256 variant, uint256 fee_bps, bool strict_bet) external
+        function createGame(uint256 bet) external returns (uint256)
+        function commit(uint256 game_id, uint256 commitment) external payable
+        function reveal(uint256 game_id, uint256 choice, uint256 blinding_factor) external
+        function distribute(uint256 game_id) external returns (uint256)
+        event GameCreated(uint256 indexed gameId, address indexed creator, uint256 bet)
+        event Winner(uint256 indexed gameId, address indexed winner, uint256 amount)
+        event Draw(uint256 indexed gameId, address playerZero, address playerOne)
+    ]"#,
+    methods {
+        // `new` would clash with the binding's own constructor
+        new(uint256, uint256, uint256, uint256, uint256, uint256, bool) as init;
+    }
+);
+
+/// The middleware used to sign and send transactions.
+pub type SignerClient = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+/// Binding for the `RPS` contract, signing with a [`StylusClient`] wallet.
+pub type RPS = RpsBinding<SignerClient>;
+
+/// Binding for the `RPS` contract over a plain provider, for calls and log queries that sign nothing.
+pub type ReadOnlyRPS = RpsBinding<Provider<Http>>;
+
+/// Connects to `rpc_url` without a wallet and returns a read-only binding for the contract deployed at `address`.
+pub fn read_only_instance(rpc_url: String, address: Address) -> eyre::Result<ReadOnlyRPS> {
+    let provider = Provider::<Http>::try_from(rpc_url)?;
+    Ok(RpsBinding::new(address, Arc::new(provider)))
+}
+
+/// A contract binding that can be attached to a deployed address.
+pub trait ContractInstance {
+    fn at(address: Address, client: Arc<SignerClient>) -> Self;
+}
+
+impl ContractInstance for RPS {
+    fn at(address: Address, client: Arc<SignerClient>) -> Self {
+        RpsBinding::new(address, client)
+    }
+}
+
+/// A provider connected to an RPC endpoint, with a wallet for signing.
+pub struct StylusClient {
+    client: Arc<SignerClient>,
+}
+
+impl StylusClient {
+    /// Connects to `rpc_url` and signs with the hex-encoded `privkey`.
+    /// The chain id is read from the node, so signed transactions are replay-protected.
+    pub async fn new(rpc_url: String, privkey: String) -> eyre::Result<Self> {
+        let provider = Provider::<Http>::try_from(rpc_url)?;
+        let chain_id = provider.get_chainid().await?.as_u64();
+        let wallet = privkey.parse::<LocalWallet>()?.with_chain_id(chain_id);
+        let client = Arc::new(SignerMiddleware::new(provider, wallet));
+        Ok(Self { client })
+    }
+
+    /// Returns a binding of type `C` for the contract deployed at `address`.
+    pub fn contract_instance<C: ContractInstance>(&self, address: Address) -> C {
+        C::at(address, self.client.clone())
+    }
+
+    /// Returns the address of the signing wallet.
+    pub fn address(&self) -> Address {
+        self.client.address()
+    }
+}
@@ -0,0 +1,42 @@
+//! Commitment hashing shared by the contract and host-side clients.
+//! It is compiled into the WASM contract and, with the `client` feature, into host builds,
+//! so both sides always agree on the preimage encoding.
+//!
+//! The preimage uses tight `abi.encodePacked` semantics over a `uint256`, a `uint256` and an `address`:
+//!
+//! | bytes   | field             | encoding                 |
+//! |---------|-------------------|--------------------------|
+//! | 0..32   | `choice`          | 32-byte big-endian       |
+//! | 32..64  | `blinding_factor` | 32-byte big-endian       |
+//! | 64..84  | `sender`          | 20 raw address bytes     |
+//!
+//! In Solidity this is `keccak256(abi.encodePacked(uint256(choice), blindingFactor, sender))`.
+//! Note that `abi.encode` would left-pad the address to 32 bytes and give a different hash.
+
+use alloy_primitives::{keccak256, Address, U256};
+
+/// Length in bytes of a commitment preimage.
+pub const PREIMAGE_LEN: usize = 32 + 32 + 20;
+
+/// Returns the exact bytes hashed into a commitment, laid out as documented on this module.
+pub fn commitment_preimage(choice: u8, blinding_factor: U256, sender: Address) -> [u8; PREIMAGE_LEN] {
+    let mut preimage = [0u8; PREIMAGE_LEN];
+    preimage[..32].copy_from_slice(&U256::from(choice).to_be_bytes::<32>());
+    preimage[32..64].copy_from_slice(&blinding_factor.to_be_bytes::<32>());
+    preimage[64..].copy_from_slice(sender.as_slice());
+    preimage
+}
+
+/// Computes the commitment a player submits before revealing `choice`.
+///
+/// Binding the sender means a commitment copied from another player's transaction can't be reused.
+pub fn compute_commitment(choice: u8, blinding_factor: U256, sender: Address) -> U256 {
+    U256::from_be_bytes(keccak256(commitment_preimage(choice, blinding_factor, sender)).0)
+}
+
+/// Returns whether revealing `choice` with `blinding_factor` from `sender` opens `commitment`.
+///
+/// This is the check `reveal` makes, so any change to a single field fails it.
+pub fn verify_commitment(commitment: U256, choice: u8, blinding_factor: U256, sender: Address) -> bool {
+    compute_commitment(choice, blinding_factor, sender) == commitment
+}
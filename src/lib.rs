@@ -1,53 +1,92 @@
 // Rock Paper Scissors Game Tutorial
 //
 // This tutorial demonstrates how to create a simple Rock Paper Scissors game using the Stylus SDK and Rust.
-// The game allows two players to commit their choices (rock, paper, or scissors) and then determines the winner based on the classic rules of the game.
+// The game allows two players to commit to hidden choices (rock, paper, or scissors), reveal them, and then determines the winner based on the classic rules of the game.
+// It can also be played with the extended Rock-Paper-Scissors-Lizard-Spock rules.
 //
 // Steps:
-// 1. Define the `Choice` enum to represent the possible choices: None, Rock, Paper, or Scissors.
-// 2. Implement the `From` trait for converting between `U256` and `Choice`.
-// 3. Define the `RPS` struct using the `sol_storage!` macro to store the game state.
-// 4. Implement the `new` function to initialize the game with a bet amount.
-// 5. Implement the `lock` and `unlock` functions to control the game state.
-// 6. Implement the `commit` function to allow players to commit their choices and place bets.
+// 1. Define the `Choice` enum to represent the possible choices: None, Rock, Paper, Scissors, Lizard, or Spock.
+// 2. Implement the `TryFrom` and `From` traits for converting between `U256` and `Choice`, and likewise for the `Stage` enum.
+// 3. Define the `RPS` struct using the `sol_storage!` macro to store the state of every game.
+// 4. Implement the `new` function to initialize the contract with the number of rounds to win, the rules variant and the house fee,
+//    and the `create_game` function to open a game with a bet amount.
+// 5. Implement the `lock` and `unlock` functions, gated on the owner, to control the game state.
+// 6. Implement the `commit` function to allow players to commit to hashed choices and place bets,
+//    and the `reveal` function to open the commitments once every player has committed.
 // 7. Implement the `distribute` function to determine the winner and distribute the winnings.
+// 8. Test the win-determination rules.
 //
 // Let's go through each step in detail:
 
-#![cfg_attr(not(feature = "export-abi"), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+// The WASM contract leaves out `std`, whose panic and formatting machinery the deployed code has no use for
+// It needs the `mini_alloc` allocator below then, so building with `--no-default-features` keeps `std` and its allocator
+#![cfg_attr(all(target_arch = "wasm32", feature = "mini-alloc"), no_std)]
+// The exported ABI chains one iterator per external function when collecting its structs and errors
+#![cfg_attr(feature = "export-abi", recursion_limit = "256")]
 
 extern crate alloc;
 
+// Use the small `mini_alloc` bump allocator in the WASM build, which keeps the deployed code smaller
+// Building with `--no-default-features` falls back to Rust's default allocator
+#[cfg(all(target_arch = "wasm32", feature = "mini-alloc"))]
+#[global_allocator]
+static ALLOC: mini_alloc::MiniAlloc = mini_alloc::MiniAlloc::INIT;
+
+// Without `std`, a panic simply traps, which reverts the call like any other failure
+#[cfg(all(target_arch = "wasm32", feature = "mini-alloc"))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    core::arch::wasm32::unreachable()
+}
+
+// Commitment hashing shared with host-side clients, see `examples/play.rs`
+pub mod commitment;
+
+// Host-side client for talking to a deployed contract, see `examples/play.rs`
+#[cfg(feature = "client")]
+pub mod client;
+
+use alloy_sol_types::sol;
 use stylus_sdk::{
-    alloy_primitives::U256,
+    alloy_primitives::{Address, U16, U256, U8},
     prelude::*,
-    msg, call,
+    msg, call, evm, block, contract,
 };
 
 // Define the `Choice` enum to represent the possible choices in the game
-// The choices are: None, Rock, Paper, or Scissors
+// The choices are: None, Rock, Paper, or Scissors, plus Lizard and Spock in the extended variant
 #[derive(Copy, Clone, PartialEq)]
 pub enum Choice {
     None,
     Rock,
     Paper,
     Scissors,
+    Lizard,
+    Spock,
 }
 
-// Implement the `From` trait for converting from `U256` to `Choice`
+// Implement the `TryFrom` trait for converting from `U256` to `Choice`
 // This allows us to convert a `U256` value to a `Choice` enum variant
-impl From<U256> for Choice {
-    fn from(value: U256) -> Self {
+// Values outside 0..=5 return an `InvalidChoice` error instead of panicking, so callers get a clean revert reason
+impl TryFrom<U256> for Choice {
+    type Error = RpsError;
+
+    fn try_from(value: U256) -> Result<Self, Self::Error> {
         if value == U256::from(0) {
-            Choice::None
+            Ok(Choice::None)
         } else if value == U256::from(1) {
-            Choice::Rock
+            Ok(Choice::Rock)
         } else if value == U256::from(2) {
-            Choice::Paper
+            Ok(Choice::Paper)
         } else if value == U256::from(3) {
-            Choice::Scissors
+            Ok(Choice::Scissors)
+        } else if value == U256::from(4) {
+            Ok(Choice::Lizard)
+        } else if value == U256::from(5) {
+            Ok(Choice::Spock)
         } else {
-            panic!("Invalid choice"); // Panic if the value is not a valid choice
+            Err(InvalidChoice {}.into()) // Return an error if the value is not a valid choice
         }
     }
 }
@@ -61,21 +100,426 @@ impl From<Choice> for U256 {
             Choice::Rock => U256::from(1),
             Choice::Paper => U256::from(2),
             Choice::Scissors => U256::from(3),
+            Choice::Lizard => U256::from(4),
+            Choice::Spock => U256::from(5),
+        }
+    }
+}
+
+// Read a stored choice for settlement, treating a value that isn't a valid choice as None
+// `reveal` only ever stores valid choices, but a bad write must not make a game impossible to distribute,
+// so the player it belongs to simply forfeits the round as if they hadn't revealed
+pub fn stored_choice(value: U256) -> Choice {
+    Choice::try_from(value).unwrap_or(Choice::None)
+}
+
+// Define the `Stage` enum to represent where a game is in its round
+// The stages are: FirstCommit, SecondCommit, and Distribute
+#[derive(Copy, Clone, PartialEq)]
+pub enum Stage {
+    FirstCommit,
+    SecondCommit,
+    Distribute,
+}
+
+// Implement the `TryFrom` trait for converting from `U256` to `Stage`
+// Values outside 0..=2 return an `InvalidStage` error instead of panicking, with the last valid stage as `expected`
+impl TryFrom<U256> for Stage {
+    type Error = RpsError;
+
+    fn try_from(value: U256) -> Result<Self, Self::Error> {
+        if value == U256::from(0) {
+            Ok(Stage::FirstCommit)
+        } else if value == U256::from(1) {
+            Ok(Stage::SecondCommit)
+        } else if value == U256::from(2) {
+            Ok(Stage::Distribute)
+        } else {
+            Err(InvalidStage { expected: U256::from(2), actual: value }.into()) // Return an error if the value is not a valid stage
+        }
+    }
+}
+
+// Implement the `From` trait for converting from `Stage` to `U256`
+// In a new game the stored value is also the number of players seated so far, which `commit_to` uses as the next free slot;
+// in a series it isn't, since seats are held between rounds
+impl From<Stage> for U256 {
+    fn from(stage: Stage) -> Self {
+        match stage {
+            Stage::FirstCommit => U256::from(0),
+            Stage::SecondCommit => U256::from(1),
+            Stage::Distribute => U256::from(2),
+        }
+    }
+}
+
+// Define the `WinResult` enum to represent the outcome of a round
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WinResult {
+    PlayerZero,
+    PlayerOne,
+    Draw,
+}
+
+// Decide the winner of a round from the choices of player 0 and player 1
+// This is a pure function, so off-chain simulators can reuse exactly the same rules as `distribute`
+// The table covers the Lizard-Spock rules too; in classic mode `reveal` never accepts Lizard or Spock,
+// so only the Rock-Paper-Scissors rows can be reached
+// A `None` loses to any real choice, as if that player had forfeited; if neither player revealed, the round is void
+// and is treated like a draw, so both bets are refunded
+pub fn decide_winner(p0: Choice, p1: Choice) -> WinResult {
+    match (p0, p1) {
+        (Choice::None, Choice::None) => WinResult::Draw,
+        (Choice::None, _) => WinResult::PlayerOne,
+        (_, Choice::None) => WinResult::PlayerZero,
+        (Choice::Rock, Choice::Scissors) | (Choice::Paper, Choice::Rock) | (Choice::Scissors, Choice::Paper) => WinResult::PlayerZero,
+        (Choice::Rock, Choice::Paper) | (Choice::Paper, Choice::Scissors) | (Choice::Scissors, Choice::Rock) => WinResult::PlayerOne,
+        (Choice::Rock, Choice::Lizard) | (Choice::Lizard, Choice::Spock) | (Choice::Spock, Choice::Scissors)
+        | (Choice::Scissors, Choice::Lizard) | (Choice::Lizard, Choice::Paper) | (Choice::Paper, Choice::Spock)
+        | (Choice::Spock, Choice::Rock) => WinResult::PlayerZero,
+        (Choice::Lizard, Choice::Rock) | (Choice::Spock, Choice::Lizard) | (Choice::Scissors, Choice::Spock)
+        | (Choice::Lizard, Choice::Scissors) | (Choice::Paper, Choice::Lizard) | (Choice::Spock, Choice::Paper)
+        | (Choice::Rock, Choice::Spock) => WinResult::PlayerOne,
+        _ => WinResult::Draw, // Only equal choices are left
+    }
+}
+
+// Whether a choice can be played: Rock, Paper or Scissors, plus Lizard and Spock with the `extended` rules
+// `None` only stands for a missing reveal and is never a playable choice
+pub fn allowed_choice(choice: Choice, extended: bool) -> bool {
+    match choice {
+        Choice::Rock | Choice::Paper | Choice::Scissors => true,
+        Choice::Lizard | Choice::Spock => extended,
+        Choice::None => false,
+    }
+}
+
+// The outcome `distribute` returns when nobody wins the round
+// No game has this many slots, so it can't be mistaken for a winning slot
+pub const NO_WINNER: U256 = U256::MAX;
+
+// The number of blocks between arming the emergency withdrawal and being able to use it
+// It is a constant so anyone can audit how long players have to withdraw before the owner can sweep the contract
+pub const EMERGENCY_DELAY: u64 = 50_400;
+
+// The number of blocks a game may run from its first commit before anyone can expire it, until the owner changes it
+// At 12 seconds per block this is four weeks, long enough for any series played in good faith
+pub const DEFAULT_MAX_GAME_BLOCKS: u64 = 201_600;
+
+// The number of blocks between the last commit of a round and the first block its players may reveal in
+// One block keeps a reveal out of the block of the last commit, see `reveal`
+pub const REVEAL_GAP: u64 = 1;
+
+// The contract version, encoded as `major * 10000 + minor * 100 + patch`, so 0.2.0 is 200
+// It follows the crate version, which is bumped for each release that changes the contract's ABI or behaviour
+pub const VERSION: u64 = 200;
+
+// Declare events with the `sol!` macro, keeping their Solidity declarations for the exported ABI
+// The Stylus SDK only exports functions, errors and structs, so `ExportedAbi` adds the events itself
+macro_rules! events {
+    ($(event $name:ident($($param:tt)*);)*) => {
+        sol! {
+            $(event $name($($param)*);)*
         }
+
+        #[cfg(feature = "export-abi")]
+        const EVENT_DECLARATIONS: &[&str] = &[$(concat!("event ", stringify!($name), "(", stringify!($($param)*), ");")),*];
+    };
+}
+
+// Define the events emitted by the contract using the `sol!` macro
+// Events let off-chain indexers follow the games without polling storage
+// Every game event is indexed by its `gameId`, so indexers can follow one table at a time
+events! {
+    event GameCreated(uint256 indexed gameId, address indexed creator, uint256 bet);
+    event Committed(uint256 indexed gameId, address indexed player, uint256 playerIndex);
+    event Revealed(uint256 indexed gameId, address indexed player, uint256 choice);
+    event StageChanged(uint256 indexed gameId, uint256 oldStage, uint256 newStage);
+    event Winner(uint256 indexed gameId, address indexed winner, uint256 amount);
+    event Draw(uint256 indexed gameId, address playerZero, address playerOne);
+    event RoundWon(uint256 indexed gameId, address indexed winner, uint256 score);
+    event Locked(address indexed by);
+    event Unlocked(address indexed by);
+    event OwnershipTransferred(address indexed previous, address indexed next);
+    event FeeCollected(address indexed owner, uint256 amount);
+    event GameExpired(uint256 indexed gameId, address refundedTo);
+    event GameTimedOut(uint256 indexed gameId, address[] refunded);
+    event MaxGameBlocksChanged(uint256 blocks);
+    event EmergencyArmed(uint256 unlockBlock);
+    event EmergencyWithdrawn(address indexed to, uint256 amount);
+    event Swept(address indexed to, uint256 amount);
+    event PaymentDeferred(address indexed to, uint256 amount);
+    event Withdrawal(address indexed player, uint256 amount);
+    event FeeChanged(uint256 feeBps);
+    event CommitSpanChanged(uint256 span);
+}
+
+// Define the errors returned by the contract using the `sol!` macro
+// Each error is ABI-encoded with its own selector, so callers can decode and match on it
+sol! {
+    error InvalidChoice();
+    error InvalidRounds();
+    error InvalidVariant();
+    error FeeTooHigh();
+    error InvalidBetBounds();
+    error ZeroBet();
+    error BetOutOfRange(uint256 bet, uint256 minBet, uint256 maxBet);
+    error ContractLocked();
+    error UnknownGame();
+    error InvalidStage(uint256 expected, uint256 actual);
+    error InsufficientFunds();
+    error ExactBetRequired();
+    error NotAPlayer();
+    error SelfPlay();
+    error AlreadyCommitted();
+    error InvalidCommitment();
+    error InvalidReveal();
+    error AlreadyRevealed();
+    error RevealPending();
+    error OpponentMissing();
+    error RevealTooEarly(uint256 revealBlock);
+    error GameInProgress();
+    error DeadlineNotReached();
+    error InvalidDuration();
+    error CommitWindowClosed();
+    error GameTooOld();
+    error RevealWindowClosed();
+    error NothingToWithdraw();
+    error AlreadyInitialized();
+    error NotOwner();
+    error NotPendingOwner();
+    error EmergencyNotArmed();
+    error EmergencyActive(uint256 unlockBlock);
+    error TimelockActive(uint256 unlockBlock);
+    error ReentrantCall();
+    error TransferFailed(address to, uint256 amount);
+    error BalanceOverflow();
+}
+
+// Define the `RpsError` enum wrapping every error the contract can return
+// Deriving `SolidityError` lets functions return it directly and adds the errors to the exported ABI
+#[derive(SolidityError)]
+pub enum RpsError {
+    InvalidChoice(InvalidChoice),
+    InvalidRounds(InvalidRounds),
+    InvalidVariant(InvalidVariant),
+    FeeTooHigh(FeeTooHigh),
+    InvalidBetBounds(InvalidBetBounds),
+    ZeroBet(ZeroBet),
+    BetOutOfRange(BetOutOfRange),
+    ContractLocked(ContractLocked),
+    UnknownGame(UnknownGame),
+    InvalidStage(InvalidStage),
+    InsufficientFunds(InsufficientFunds),
+    ExactBetRequired(ExactBetRequired),
+    NotAPlayer(NotAPlayer),
+    SelfPlay(SelfPlay),
+    AlreadyCommitted(AlreadyCommitted),
+    InvalidCommitment(InvalidCommitment),
+    InvalidReveal(InvalidReveal),
+    AlreadyRevealed(AlreadyRevealed),
+    RevealPending(RevealPending),
+    OpponentMissing(OpponentMissing),
+    RevealTooEarly(RevealTooEarly),
+    GameInProgress(GameInProgress),
+    DeadlineNotReached(DeadlineNotReached),
+    InvalidDuration(InvalidDuration),
+    CommitWindowClosed(CommitWindowClosed),
+    GameTooOld(GameTooOld),
+    RevealWindowClosed(RevealWindowClosed),
+    NothingToWithdraw(NothingToWithdraw),
+    AlreadyInitialized(AlreadyInitialized),
+    NotOwner(NotOwner),
+    NotPendingOwner(NotPendingOwner),
+    EmergencyNotArmed(EmergencyNotArmed),
+    EmergencyActive(EmergencyActive),
+    TimelockActive(TimelockActive),
+    ReentrantCall(ReentrantCall),
+    TransferFailed(TransferFailed),
+    BalanceOverflow(BalanceOverflow),
+}
+
+// The Solidity interface printed by `cargo stylus export-abi`: the SDK's generated interface, with the events added
+// See `src/main.rs`, which prints it under the license and pragma
+#[cfg(feature = "export-abi")]
+pub struct ExportedAbi;
+
+#[cfg(feature = "export-abi")]
+impl core::fmt::Display for ExportedAbi {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        struct Generated;
+        impl core::fmt::Display for Generated {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                <RPS as stylus_sdk::abi::GenerateAbi>::fmt_abi(f)
+            }
+        }
+
+        // Declare the events at the end of the interface, after the errors and structs
+        // `stringify!` may break a long declaration over several lines, so each one is put back on a single line
+        let interface = Generated.to_string();
+        let end = interface.rfind('}').ok_or(core::fmt::Error)?;
+        write!(f, "{}", &interface[..end])?;
+        for event in EVENT_DECLARATIONS {
+            writeln!(f, "\n    {}", event.split_whitespace().collect::<Vec<_>>().join(" "))?;
+        }
+        write!(f, "{}", &interface[end..])
     }
 }
 
+// Derive the id of the game `creator` creates when it has already created `nonce` games
+// The id is `keccak256(abi.encodePacked(creator, nonce))`, so ids of different creators can't collide or be guessed in order
+pub fn derive_game_id(creator: Address, nonce: U256) -> U256 {
+    let mut preimage = [0u8; 20 + 32];
+    preimage[..20].copy_from_slice(creator.as_slice());
+    preimage[20..].copy_from_slice(&nonce.to_be_bytes::<32>());
+    U256::from_be_bytes(alloy_primitives::keccak256(preimage).0)
+}
+
+// Calculate a round's winnings, the bets of both players
+// The multiplication is checked, so an enormous bet fails cleanly instead of wrapping to a wrong payout
+pub fn round_winnings(bet: U256) -> Result<U256, RpsError> {
+    bet.checked_mul(U256::from(2)).ok_or(BalanceOverflow {}.into())
+}
+
+// The escrow total `pay` writes before sending `amount`, and the one it writes back if the transfer fails
+// The release is written before the call, so a delivered payment needs nothing but the guard flag restored afterwards;
+// a failed one is credited to the recipient instead, and so counted as escrowed again
+// The release saturates, so an accounting slip can only make `sweep_unaccounted` sweep less, never block a payment
+fn payment_escrow(escrowed: U256, amount: U256) -> (U256, U256) {
+    let released = escrowed.saturating_sub(amount);
+    (released, released + amount) // Can't overflow: it is at most the larger of `escrowed` and `amount`
+}
+
+// The part of the contract's balance that isn't escrowed, which `sweep_unaccounted` may send away
+// It is zero whenever the balance doesn't exceed the escrow, so a sweep can never dip into escrowed funds
+fn unaccounted(balance: U256, escrowed: U256) -> U256 {
+    balance.saturating_sub(escrowed)
+}
+
+// The first block players may reveal in, when the last commit of their round landed in `commit_block`
+// Saturates, so an enormous gap only delays reveals instead of wrapping around to allow them early
+pub fn reveal_opens_at(commit_block: u64, gap: U256) -> U256 {
+    U256::from(commit_block).saturating_add(gap)
+}
+
+// The last block a game whose first commit landed in block `start` may run in, before `expire_game` may refund it
+// Saturates, so an enormous limit set by the owner simply never expires the game
+pub fn expiry_block(start: U256, max_game_blocks: U256) -> U256 {
+    start.saturating_add(max_game_blocks)
+}
+
+// Split what a game's finished rounds left in escrow, its series pot, when the game expires
+// `committed` tells which of the two players has a bet committed to the stalled round, and `deadline_passed` whether
+// the opponent's commit deadline for it has passed
+// Both players staked the same in every finished round, so each normally gets their own stakes back, half of `shared`
+// with the odd wei to player 1; but if only one of them committed and the other let their deadline pass, the other
+// stalled the game and forfeits their half. A player still within their deadline, e.g. when the owner lowered the limit
+// or armed the emergency, has stalled nothing and keeps their half
+pub fn expiry_shares(shared: U256, committed: [bool; 2], deadline_passed: bool) -> (U256, U256) {
+    match committed {
+        [true, false] if deadline_passed => (shared, U256::from(0)),
+        [false, true] if deadline_passed => (U256::from(0), shared),
+        _ => {
+            let half = shared / U256::from(2);
+            (half, shared - half)
+        }
+    }
+}
+
+// Whether every seat of a game is taken, given its seated addresses in slot order
+pub fn all_seated(seated: impl IntoIterator<Item = Address>) -> bool {
+    seated.into_iter().all(|address| address != Address::ZERO)
+}
+
+// Find the slot of `player` among the addresses seated in a game, in slot order
+// The zero address marks an empty slot and is never seated
+pub fn seat_of(seated: impl IntoIterator<Item = Address>, player: Address) -> Option<U256> {
+    if player == Address::ZERO {
+        return None;
+    }
+    seated.into_iter().position(|address| address == player).map(U256::from)
+}
+
+// The one player a game's reveals still wait for, given its seated addresses and which of them revealed, in slot order
+// That is the player who hasn't revealed once the other has; while neither has, either may go first, and once both have,
+// anyone may call `distribute`, so no single player is up and the zero address is returned
+pub fn pending_revealer(seated: [Address; 2], revealed: [bool; 2]) -> Address {
+    match revealed {
+        [true, false] => seated[1],
+        [false, true] => seated[0],
+        _ => Address::ZERO,
+    }
+}
+
+// Check that the contract's lock may still change, given the block `arm_emergency` set, zero if never armed
+// Arming the emergency is final: from then on, before and after the owner's sweep, nothing can lift, replace or re-arm its lock,
+// so no new stakes can come in that the warning didn't cover, and no old claim can be paid out of new stakes
+pub fn check_lock_change(emergency_unlock_block: U256) -> Result<(), RpsError> {
+    if emergency_unlock_block != U256::from(0) {
+        return Err(EmergencyActive { unlockBlock: emergency_unlock_block }.into());
+    }
+    Ok(())
+}
+
+// Whether a refund path's wait, ending at block `deadline`, is over at `block_number`
+// Once the emergency is armed nothing waits: the owner's sweep may come before a game's limit or a stalled commit's deadline,
+// so every escrowed bet must be claimable before it
+pub fn wait_over(deadline: U256, block_number: U256, emergency_armed: bool) -> bool {
+    emergency_armed || block_number > deadline
+}
+
+// Check that a bet is non-zero and lies within the operator's bounds, both inclusive
+// This is a pure function, so the bounds can be tested without a deployed contract
+pub fn check_bet(bet: U256, min_bet: U256, max_bet: U256) -> Result<(), RpsError> {
+    if bet == U256::from(0) {
+        return Err(ZeroBet {}.into()); // A zero bet would make the game pointless
+    }
+    if bet < min_bet || bet > max_bet {
+        return Err(BetOutOfRange { bet, minBet: min_bet, maxBet: max_bet }.into());
+    }
+    Ok(())
+}
+
 // Define the `RPS` struct using the `sol_storage!` macro
 // This struct represents the storage layout of the contract
+// The contract hosts any number of games side by side, so all per-game state is keyed by a game id
 sol_storage! {
     #[entrypoint]
     pub struct RPS {
         mapping(address => uint256) player_balances; // Mapping to store player balances
-        mapping(uint256 => uint256) player_choices; // Mapping to store player choices
-        mapping(uint256 => address) player_addresses; // Mapping to store player addresses
-        uint256 bet; // The bet amount for the game
-        uint256 stage; // The current stage of the game
+        mapping(uint256 => mapping(uint256 => uint256)) player_commitments; // Mapping to store the commitment of each player slot per game
+        mapping(uint256 => mapping(uint256 => uint256)) player_choices; // Mapping to store the revealed player choices per game
+        mapping(uint256 => mapping(uint256 => address)) player_addresses; // Mapping to store player addresses per game
+        mapping(address => uint256) pending_withdrawals; // Mapping to store each address's internal balance: winnings, fees and refunds not yet withdrawn
+        mapping(uint256 => mapping(uint256 => uint256)) scores; // Mapping to store the rounds won by each player in a game's current series
+        mapping(uint256 => address) creators; // Mapping to store the address that created each game
+        mapping(uint256 => uint256) bet; // Mapping to store the bet amount of each game
+        mapping(uint256 => uint256) pot; // Mapping to store the winnings accumulated over the decided rounds of each game's series
+        mapping(uint256 => uint256) stage; // Mapping to store the current stage of each game
+        mapping(uint256 => uint256) commit_deadlines; // Mapping to store the last block each game's second player may commit in
+        mapping(uint256 => uint256) reveal_deadlines; // Mapping to store the last block each game's players may reveal in
+        mapping(uint256 => uint256) reveal_open_blocks; // Mapping to store the first block each game's players may reveal in
+        mapping(uint256 => uint256) game_start_blocks; // Mapping to store the block of each running game's first commit, zero between games
+        mapping(address => uint256) creator_nonces; // Mapping to store how many games each address has created, for deriving game ids
+        uint256 total_escrowed; // The ETH sent to payable functions and not yet paid out, so it belongs to players or the owner
+        uint256 min_bet; // The smallest bet a game may be created with
+        uint256 max_bet; // The largest bet a game may be created with
+        uint256 max_game_blocks; // The number of blocks a game may run from its first commit before `expire_game` refunds it
+        uint256 commit_span; // The number of blocks the second player has to commit after the first, and the players have to reveal
+        uint256 rounds_to_win; // The number of rounds a player must win to take a series
+        address owner; // The address that changes the settings, collects the fees and may arm the emergency withdrawal
+        address pending_owner; // The address nominated to become the next owner
+        uint256 emergency_unlock_block; // The block after which the owner may sweep the contract, zero until armed; never cleared
+
+        // The small contract-wide settings below are packed into a single storage slot, in declaration order,
+        // so the settings `commit` and `distribute` read share a slot with the reentrancy guard
+        // Each type is just wide enough for the range its setter allows
+        uint16 fee_bps; // The house fee taken from the winnings, in basis points (0 to 1000)
+        uint8 variant; // The rules variant: 0 = classic, 1 = extended (Lizard-Spock)
+        bool strict_bet; // Flag to require commits to send exactly the bet, with no excess refund
         bool locked; // Flag to indicate if the contract is locked
+        bool in_call; // Reentrancy guard, set while ETH is being sent to an external address
+        bool initialized; // Flag set by the first call to `new`, so the contract can't be reset afterwards
     }
 }
 
@@ -83,81 +527,1327 @@ sol_storage! {
 #[external]
 impl RPS {
     // The `new` function is used to initialize the contract
-    // It takes the bet amount as a parameter and sets the initial state
-    pub fn new(&mut self, bet: U256) -> Result<(), Vec<u8>> {
-        self.bet.set(bet); // Set the bet amount
-        self.stage.set(U256::from(0)); // Set the initial stage to FirstCommit
+    // It takes the rules shared by every game and sets the initial state; games themselves are opened with `create_game`
+    // Every game's bet must lie between `min_bet` and `max_bet`, both inclusive
+    // Once a player commits, the opponent has `commit_span` blocks to follow before the game can be reclaimed,
+    // and once a round's reveals open, its players have `commit_span` blocks to reveal; it can't be 0
+    // Passing `rounds = 1` plays single games, `rounds = 2` best-of-three series, and so on
+    // The `variant` selects the rules: 0 for classic Rock-Paper-Scissors, 1 for Rock-Paper-Scissors-Lizard-Spock
+    // The `fee_bps` is the owner's cut of the winnings in basis points, capped at 1000 (10%)
+    // With `strict_bet` set, commits must send exactly the bet instead of having any excess refunded
+    // The caller becomes the owner of the contract
+    // It can only be called once; later changes go through the dedicated setters and their own guards
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
+    pub fn new(&mut self, min_bet: U256, max_bet: U256, commit_span: U256, rounds: U256, variant: U256, fee_bps: U256, strict_bet: bool) -> Result<(), RpsError> {
+        if self.initialized.get() {
+            return Err(AlreadyInitialized {}.into()); // Return an error if the contract has already been set up
+        }
+
+        if min_bet == U256::from(0) {
+            return Err(ZeroBet {}.into()); // Return an error if free games would be allowed
+        }
+
+        if min_bet > max_bet {
+            return Err(InvalidBetBounds {}.into()); // Return an error if no bet could ever be placed
+        }
+
+        if rounds == U256::from(0) {
+            return Err(InvalidRounds {}.into()); // Return an error if a series could never be won
+        }
+
+        if variant > U256::from(1) {
+            return Err(InvalidVariant {}.into()); // Return an error if the variant is neither classic nor extended
+        }
+
+        if commit_span == U256::from(0) {
+            return Err(InvalidDuration {}.into()); // Return an error if no commit or reveal window could ever stay open
+        }
+
+        if fee_bps > U256::from(1000) {
+            return Err(FeeTooHigh {}.into()); // Return an error if the fee is above 10%
+        }
+
+        self.min_bet.set(min_bet); // Set the smallest allowed bet
+        self.max_bet.set(max_bet); // Set the largest allowed bet
+        self.commit_span.set(commit_span); // Set the time the second player has to commit
+        self.max_game_blocks.set(U256::from(DEFAULT_MAX_GAME_BLOCKS)); // Set how long a game may run before it can be expired
+        self.rounds_to_win.set(rounds); // Set the number of rounds needed to win
+        self.variant.set(U8::from(variant)); // Set the rules variant
+        self.fee_bps.set(U16::from(fee_bps)); // Set the house fee
+        self.strict_bet.set(strict_bet); // Set whether commits must send the exact bet
         self.locked.set(false); // Set the locked flag to false
+        self.owner.set(msg::sender()); // Set the owner to the caller
+        self.initialized.set(true); // Refuse any further call to `new`
         Ok(())
     }
 
+    // The `create_game` function opens a new game with the given bet and returns its id
+    // The id is derived from the caller and their creation count, see `derive_game_id`, so clients can compute it in advance
+    // The game starts in the FirstCommit stage, and once finished it can be played again under the same id
+    // A `GameCreated` event is emitted with the caller as the creator
+    pub fn create_game(&mut self, bet: U256) -> Result<U256, RpsError> {
+        if self.locked.get() {
+            return Err(ContractLocked {}.into()); // Return an error if the contract is locked
+        }
+
+        check_bet(bet, self.min_bet.get(), self.max_bet.get())?; // Return an error if the bet is out of bounds
+
+        let nonce = self.creator_nonces.get(msg::sender());
+        let game_id = derive_game_id(msg::sender(), nonce); // Get a fresh game id
+        self.creator_nonces.insert(msg::sender(), nonce + U256::from(1));
+
+        self.creators.insert(game_id, msg::sender()); // Store the game's creator
+        self.bet.insert(game_id, bet); // Set the bet amount
+        self.set_stage(game_id, Stage::FirstCommit); // Set the initial stage
+        evm::log(GameCreated { gameId: game_id, creator: msg::sender(), bet }); // Announce the new game
+
+        Ok(game_id)
+    }
+
     // The `lock` function is used to lock the contract
-    // It sets the locked flag to true
-    pub fn lock(&mut self) -> Result<(), Vec<u8>> {
+    // It sets the locked flag to true and can only be called by the owner
+    // While locked, no game can be created or committed to; running games can still be revealed and distributed, and refunds still go out,
+    // so a lock never makes a player miss a reveal deadline
+    // It is rejected once the emergency is armed, whose lock it must not replace, and does nothing while already locked
+    pub fn lock(&mut self) -> Result<(), RpsError> {
+        self.only_owner()?;
+        self.check_lock_change()?;
+        if self.locked.get() {
+            return Ok(()); // Nothing changes, so nothing is announced
+        }
+
         self.locked.set(true);
+        evm::log(Locked { by: msg::sender() }); // Announce why commits now revert
         Ok(())
     }
 
     // The `unlock` function is used to unlock the contract
-    // It sets the locked flag to false
-    pub fn unlock(&mut self) -> Result<(), Vec<u8>> {
+    // It sets the locked flag to false and can only be called by the owner
+    // It is rejected once the emergency is armed, which keeps the contract locked for good, and does nothing while not locked
+    pub fn unlock(&mut self) -> Result<(), RpsError> {
+        self.only_owner()?;
+        self.check_lock_change()?;
+        if !self.locked.get() {
+            return Ok(()); // Nothing changes, so nothing is announced
+        }
+
         self.locked.set(false);
+        evm::log(Unlocked { by: msg::sender() }); // Announce that games are open again
         Ok(())
     }
 
-    // The `commit` function is used by players to commit their choices and place bets
+    // The `owner` function returns the address allowed to change the settings and lock the contract
+    pub fn owner(&self) -> Result<Address, RpsError> {
+        Ok(self.owner.get())
+    }
+
+    // The `transfer_ownership` function lets the owner nominate a new owner
+    // Ownership only moves once the nominee calls `accept_ownership`, so it can't be handed to an unusable address
+    pub fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), RpsError> {
+        self.only_owner()?;
+        self.pending_owner.set(new_owner);
+        Ok(())
+    }
+
+    // The `accept_ownership` function completes a transfer started by `transfer_ownership`
+    // It can only be called by the nominated address
+    pub fn accept_ownership(&mut self) -> Result<(), RpsError> {
+        if msg::sender() != self.pending_owner.get() {
+            return Err(NotPendingOwner {}.into()); // Return an error if the caller wasn't nominated
+        }
+
+        let previous = self.owner.get();
+        self.owner.set(msg::sender()); // Move ownership to the nominee
+        self.pending_owner.set(Address::ZERO); // Clear the nomination
+        evm::log(OwnershipTransferred { previous, next: msg::sender() }); // Announce the handover
+
+        Ok(())
+    }
+
+    // The `arm_emergency` function starts the timelock on the owner's emergency withdrawal
+    // It also locks the contract for good, so no new stakes come in while players withdraw theirs, nor after the sweep;
+    // running games can still be revealed and distributed, and refunds still go out, as under any lock;
+    // `expire_game` and `reclaim_stalled` stop waiting for their deadlines, so no escrow is left for the sweep to take
+    // It can only be called once, see `check_lock_change`
+    pub fn arm_emergency(&mut self) -> Result<(), RpsError> {
+        self.only_owner()?;
+        self.check_lock_change()?;
+
+        let unlock_block = U256::from(block::number()) + U256::from(EMERGENCY_DELAY);
+        self.emergency_unlock_block.set(unlock_block);
+        self.locked.set(true);
+        evm::log(EmergencyArmed { unlockBlock: unlock_block }); // Warn players before any funds can move
+
+        Ok(())
+    }
+
+    // The `emergency_withdraw` function sends the contract's whole balance to the owner
+    // It is a last-resort recovery path for funds stuck by a bug, and only works once the armed timelock has passed
+    // The contract stays locked afterwards, so the balances and pots left in storage can't be paid out of new stakes;
+    // a later call only sweeps ETH forced into the contract since
+    pub fn emergency_withdraw(&mut self) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+        self.only_owner()?;
+
+        let unlock_block = self.emergency_unlock_block.get();
+        if unlock_block == U256::from(0) {
+            return Err(EmergencyNotArmed {}.into()); // Return an error unless `arm_emergency` was called
+        }
+
+        if U256::from(block::number()) <= unlock_block {
+            return Err(TimelockActive { unlockBlock: unlock_block }.into()); // Return an error while players may still withdraw
+        }
+
+        let owner = self.owner.get();
+        let amount = contract::balance();
+        self.total_escrowed.set(U256::from(0)); // Everything escrowed goes out with the sweep
+        evm::log(EmergencyWithdrawn { to: owner, amount }); // Record the sweep
+        self.guarded_transfer(owner, amount)?;
+
+        Ok(())
+    }
+
+    // The `sweep_unaccounted` function lets the owner send ETH that no game or balance accounts for to `to`
+    // That is ETH forced into the contract without a payable call, e.g. by `selfdestruct`; escrowed funds are never touched
+    pub fn sweep_unaccounted(&mut self, to: Address) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+        self.only_owner()?;
+
+        let amount = unaccounted(contract::balance(), self.total_escrowed.get());
+        if amount == U256::from(0) {
+            return Err(NothingToWithdraw {}.into()); // Return an error unless there are stray funds
+        }
+
+        evm::log(Swept { to, amount });
+        self.guarded_transfer(to, amount) // Not released from the escrow, which never held it
+    }
+
+    // The `set_fee` function lets the owner set the house fee taken from a decided game's pot
+    // The fee is in basis points and capped at 1000 (10%), as in `new`; it applies to every series paid out afterwards
+    pub fn set_fee(&mut self, fee_bps: U256) -> Result<(), RpsError> {
+        self.only_owner()?;
+
+        if fee_bps > U256::from(1000) {
+            return Err(FeeTooHigh {}.into()); // Return an error if the fee is above 10%
+        }
+
+        self.fee_bps.set(U16::from(fee_bps));
+        evm::log(FeeChanged { feeBps: fee_bps }); // Announce the new fee
+        Ok(())
+    }
+
+    // The `set_commit_span` function lets the owner change how many blocks a second player has to commit,
+    // which is also how many blocks the players have to reveal once a round's reveals open
+    // A deadline is fixed when its window opens, so running commit and reveal windows are never shortened
+    pub fn set_commit_span(&mut self, span: U256) -> Result<(), RpsError> {
+        self.only_owner()?;
+
+        if span == U256::from(0) {
+            return Err(InvalidDuration {}.into()); // Return an error if no commit or reveal window could ever stay open
+        }
+
+        self.commit_span.set(span);
+        evm::log(CommitSpanChanged { span }); // Announce the new span
+        Ok(())
+    }
+
+    // The `set_max_game_blocks` function lets the owner change how long a game may run before `expire_game` refunds it
+    // The limit is read on every commit and expiry, so it applies to running games too: lowering it can close a running game
+    // to further commits and let anyone expire it at once. Expiring refunds every committed bet and splits the series pot
+    // evenly, except that a player whose commit deadline has passed forfeits their half, see `expiry_shares`
+    pub fn set_max_game_blocks(&mut self, blocks: U256) -> Result<(), RpsError> {
+        self.only_owner()?;
+
+        if blocks == U256::from(0) {
+            return Err(InvalidDuration {}.into()); // Return an error if every game could be expired right away
+        }
+
+        self.max_game_blocks.set(blocks);
+        evm::log(MaxGameBlocksChanged { blocks }); // Announce the new limit
+        Ok(())
+    }
+
+    // The `commit` function is used by players to commit to a hidden choice and place bets in a game
+    // The `commitment` is `compute_commitment(choice, blinding_factor, player)` from the `commitment` module,
+    // so the choice can't be read, or copied by another player, until the player reveals it
     // It is marked as `#[payable]` to allow players to send Ether along with their commitments
     #[payable]
-    pub fn commit(&mut self, choice: U256) -> Result<(), Vec<u8>> {
-        if self.locked.get() {
-            return Err("Contract is locked".into()); // Return an error if the contract is locked
+    pub fn commit(&mut self, game_id: U256, commitment: U256) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+        self.escrow_value()?;
+        self.commit_to(game_id, msg::sender(), commitment, msg::value())
+    }
+
+    // The `reveal` function opens the caller's commitment once every seat of the game is committed
+    // The choice is checked against the commitment and the rules variant before it is stored
+    // Reveals after the reveal deadline are rejected: a player who hasn't revealed by then is treated as having chosen None,
+    // and so loses the round
+    pub fn reveal(&mut self, game_id: U256, choice: U256, blinding_factor: U256) -> Result<(), RpsError> {
+        self.require_game(game_id)?;
+        self.require_stage(game_id, Stage::Distribute)?; // Return an error until every player has committed
+
+        let index = self.require_participant(game_id, msg::sender())?; // Return an error if the caller isn't seated in the game
+
+        // Reaching the reveal stage implies every seat committed, but a half-seated game must never be revealed into
+        if !all_seated(self.seated(game_id)) {
+            return Err(OpponentMissing {}.into()); // Return an error if a seat is empty
         }
 
-        let player_index = self.stage.get(); // Get the current player index based on the stage
-        if player_index > U256::from(1) {
-            return Err("Invalid stage for commit".into()); // Return an error if the stage is invalid for committing
+        if self.player_choices.getter(game_id).get(index) != U256::from(0) {
+            return Err(AlreadyRevealed {}.into()); // Return an error if the caller already revealed
         }
 
-        if msg::value() < self.bet.get() {
-            return Err("Insufficient funds committed".into()); // Return an error if the committed funds are insufficient
+        // Reveals open `REVEAL_GAP` blocks after the last commit, so a reveal can't be bundled into the same block
+        // Without the gap, a builder or sequencer-side bundler could order the last commit and a reveal together,
+        // or drop the commit after seeing the reveal in the bundle, so one block of separation keeps commits final first
+        let opens = self.reveal_open_blocks.get(game_id);
+        if U256::from(block::number()) < opens {
+            return Err(RevealTooEarly { revealBlock: opens }.into()); // Return an error until the reveal window opens
         }
 
-        if msg::value() > self.bet.get() {
-            // If the player sent more than the required bet amount, refund the excess amount
-            call::transfer_eth(msg::sender(), msg::value() - self.bet.get())?;
+        if U256::from(block::number()) > self.reveal_deadlines.get(game_id) {
+            return Err(RevealWindowClosed {}.into()); // Return an error once `distribute` may settle without this reveal
         }
 
-        self.player_choices.insert(player_index, choice); // Store the player's choice
-        self.player_addresses.insert(player_index, msg::sender()); // Store the player's address
+        // Only Rock, Paper or Scissors can be revealed, plus Lizard and Spock in the extended variant
+        if !self.playable(choice) {
+            return Err(InvalidChoice {}.into()); // Return an error if the choice is out of range
+        }
 
-        self.stage.set(player_index + U256::from(1)); // Advance the stage to the next player or to the distribute stage
+        let stored = self.player_commitments.getter(game_id).get(index);
+        if !commitment::verify_commitment(stored, choice.to::<u8>(), blinding_factor, msg::sender()) {
+            return Err(InvalidReveal {}.into()); // Return an error if the choice doesn't open the commitment
+        }
+
+        self.player_choices.setter(game_id).insert(index, choice); // Store the revealed choice
+        evm::log(Revealed { gameId: game_id, player: msg::sender(), choice });
 
         Ok(())
     }
 
-    // The `distribute` function is used to determine the winner and distribute the winnings
-    pub fn distribute(&mut self) -> Result<(), Vec<u8>> {
-        if self.stage.get() != U256::from(2) {
-            return Err("Invalid stage for distribute".into()); // Return an error if the stage is not valid for distribution
+    // The `reveal_deadline` function returns the last block the players of a game may reveal in
+    // It returns 0 unless every seat is committed and the game is waiting for reveals or `distribute`
+    pub fn reveal_deadline(&self, game_id: U256) -> Result<U256, RpsError> {
+        self.require_game(game_id)?;
+
+        if self.game_stage(game_id)? != Stage::Distribute {
+            return Ok(U256::from(0));
+        }
+        Ok(self.reveal_deadlines.get(game_id))
+    }
+
+    // The `distribute` function is used to determine the winner of a game and distribute the winnings
+    // Anyone may call it, so a keeper can settle a game its players have abandoned
+    // Returns the winning slot, or `NO_WINNER` for a draw, so an `eth_call` can preview the outcome
+    // It can be called once every player has revealed, or after the reveal deadline with whatever was revealed
+    pub fn distribute(&mut self, game_id: U256) -> Result<U256, RpsError> {
+        self.non_reentrant()?;
+        self.require_game(game_id)?;
+
+        self.require_stage(game_id, Stage::Distribute)?; // Return an error unless both players have committed
+
+        if !self.all_revealed(game_id) && U256::from(block::number()) <= self.reveal_deadlines.get(game_id) {
+            return Err(RevealPending {}.into()); // Return an error while a player may still reveal
         }
 
-        // Get the choices made by the players
-        let player0_choice = Choice::from(self.player_choices.get(U256::from(0)));
-        let player1_choice = Choice::from(self.player_choices.get(U256::from(1)));
+        // Get the choices revealed by the players, None for a player who didn't reveal or whose choice is invalid
+        let player0_choice = stored_choice(self.player_choices.getter(game_id).get(U256::from(0)));
+        let player1_choice = stored_choice(self.player_choices.getter(game_id).get(U256::from(1)));
 
         // Determine the winner based on the choices made by the players
-        let winner = match (player0_choice, player1_choice) {
-            (Choice::Rock, Choice::Scissors) | (Choice::Paper, Choice::Rock) | (Choice::Scissors, Choice::Paper) => U256::from(0),
-            (Choice::Rock, Choice::Paper) | (Choice::Paper, Choice::Scissors) | (Choice::Scissors, Choice::Rock) => U256::from(1),
-            _ => return Err("Draw".into()), // Return an error if there is a draw
+        let winner = match decide_winner(player0_choice, player1_choice) {
+            WinResult::PlayerZero => U256::from(0),
+            WinResult::PlayerOne => U256::from(1),
+            WinResult::Draw => {
+                // On a draw, refund each player their bet instead of leaving the funds stuck
+                let bet = self.bet.get(game_id);
+                let player0_address = self.player_addresses.getter(game_id).get(U256::from(0));
+                let player1_address = self.player_addresses.getter(game_id).get(U256::from(1));
+
+                evm::log(Draw { gameId: game_id, playerZero: player0_address, playerOne: player1_address }); // Announce the draw
+
+                // A drawn round leaves the scores unchanged and clears the choices for a replay
+                // Outside a running series the game is over, so the players are cleared as well
+                self.clear_choices(game_id);
+                if !self.series_in_progress(game_id) {
+                    self.clear_players(game_id);
+                }
+                self.set_stage(game_id, Stage::FirstCommit); // Reset the stage to FirstCommit for a new round
+
+                // Send the refunds last, once the game is reset
+                for player in [player0_address, player1_address] {
+                    self.pay(player, bet)?;
+                }
+                return Ok(NO_WINNER);
+            }
         };
 
-        let winning_amount = self.bet.get() * U256::from(2); // Calculate the winning amount (2 times the bet)
-        let winner_address = self.player_addresses.get(winner); // Get the address of the winner
-        call::transfer_eth(winner_address, winning_amount)?; // Transfer the winnings to the winner
+        let winning_amount = round_winnings(self.bet.get(game_id))?; // Calculate the round's winnings (2 times the bet)
+        let winner_address = self.player_addresses.getter(game_id).get(winner); // Get the address of the winner
+
+        // Add the round's winnings to the series pot
+        let pot = self.pot.get(game_id).checked_add(winning_amount).ok_or(BalanceOverflow {})?;
+        let score = self.scores.getter(game_id).get(winner) + U256::from(1); // Count the round for the winner
+
+        // Clear the choices and reset the stage to FirstCommit for the next round or game
+        self.clear_choices(game_id);
+        self.set_stage(game_id, Stage::FirstCommit);
+
+        if score < self.rounds_to_win.get() {
+            // The series isn't decided yet, so keep the pot and scores for the next round
+            self.pot.insert(game_id, pot);
+            self.scores.setter(game_id).insert(winner, score);
+            evm::log(RoundWon { gameId: game_id, winner: winner_address, score }); // Announce the round's outcome
+            return Ok(winner);
+        }
+
+        // The series is decided, so pay out the pot
+        self.settle_series(game_id, winner_address, pot)?;
+        Ok(winner)
+    }
+
+    // The `cancel_game` function lets the committed player back out if no opponent has committed yet
+    // It refunds the committed bet, clears the stored commitment and address, and resets the stage
+    pub fn cancel_game(&mut self, game_id: U256) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+        self.require_game(game_id)?;
 
-        self.stage.set(U256::from(0)); // Reset the stage to FirstCommit for a new game
+        self.require_stage(game_id, Stage::SecondCommit)?; // Return an error unless exactly one player has committed
+
+        if self.series_in_progress(game_id) {
+            return Err(GameInProgress {}.into()); // Return an error if a round has already been decided
+        }
+
+        let committed_address = self.player_addresses.getter(game_id).get(self.committed_slot(game_id));
+        if msg::sender() != committed_address {
+            return Err(NotAPlayer {}.into()); // Return an error if the caller didn't commit
+        }
+
+        self.clear_choices(game_id); // Clear the stored choice
+        self.clear_players(game_id); // Clear the stored address
+        self.set_stage(game_id, Stage::FirstCommit); // Reset the stage to FirstCommit for a new game
+
+        self.pay(committed_address, self.bet.get(game_id))?; // Refund the committed bet
 
         Ok(())
     }
+
+    // The `reclaim_stalled` function refunds the committed player if no opponent commits before the deadline
+    // Anyone may call it, since the refund can only go to the player who committed, and without waiting once the emergency is armed
+    // A refund the player rejects is credited for later withdrawal instead
+    pub fn reclaim_stalled(&mut self, game_id: U256) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+        self.require_game(game_id)?;
+
+        self.require_stage(game_id, Stage::SecondCommit)?; // Return an error unless exactly one player has committed
+
+        if self.series_in_progress(game_id) {
+            return Err(GameInProgress {}.into()); // Return an error if a round has already been decided
+        }
+
+        if !wait_over(self.commit_deadlines.get(game_id), U256::from(block::number()), self.emergency_armed()) {
+            return Err(DeadlineNotReached {}.into()); // Return an error while the opponent may still commit
+        }
+
+        let committed_address = self.player_addresses.getter(game_id).get(self.committed_slot(game_id));
+        let bet = self.bet.get(game_id);
+
+        self.clear_choices(game_id); // Clear the stored choice
+        self.clear_players(game_id); // Clear the stored address
+        self.commit_deadlines.delete(game_id); // Clear the expired deadline
+        self.set_stage(game_id, Stage::FirstCommit); // Reset the stage to FirstCommit for a new game
+
+        evm::log(GameExpired { gameId: game_id, refundedTo: committed_address }); // Announce the expiry
+        self.pay(committed_address, bet)?; // Refund the committed bet
+
+        Ok(())
+    }
+
+    // The `expire_game` function refunds a game that has run longer than `max_game_blocks` since its first commit
+    // Anyone may call it, whatever stage the game is stuck in, so no game can hold its escrow forever;
+    // once the emergency is armed it needs no wait, so every running game can be refunded before the owner's sweep
+    // Every committed bet is refunded to its player, and the series pot is split by `expiry_shares`: evenly, unless only one
+    // player committed to the stalled round and the other's commit deadline has passed, when the committed player takes it all,
+    // so stalling a losing series until it expires never pays; the game is then reset, and the refunded players are listed
+    // in a `GameTimedOut` event
+    pub fn expire_game(&mut self, game_id: U256) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+        self.require_game(game_id)?;
+
+        let start = self.game_start_blocks.get(game_id);
+        let limit = expiry_block(start, self.max_game_blocks.get());
+        if start == U256::from(0) || !wait_over(limit, U256::from(block::number()), self.emergency_armed()) {
+            return Err(DeadlineNotReached {}.into()); // Return an error unless a running game has outlived the limit
+        }
+
+        // Read and clear every seat, noting who has a bet committed to the current round
+        let mut players = [Address::ZERO; 2];
+        let mut committed = [false; 2];
+        let mut refunded = alloc::vec::Vec::new();
+        for (index, player) in players.iter_mut().enumerate() {
+            let slot = U256::from(index);
+            *player = self.player_addresses.setter(game_id).take(slot);
+            self.player_choices.setter(game_id).delete(slot);
+            if self.player_commitments.setter(game_id).take(slot) != U256::from(0) {
+                committed[index] = true;
+                refunded.push(*player);
+            }
+        }
+
+        // Reset the game, taking what the finished rounds left in escrow
+        // The commit deadline is read before it is cleared; it only matters while one player has committed
+        let deadline_passed = U256::from(block::number()) > self.commit_deadlines.get(game_id);
+        let shared = self.pot.take(game_id);
+        self.scores.setter(game_id).delete(U256::from(0));
+        self.scores.setter(game_id).delete(U256::from(1));
+        self.commit_deadlines.delete(game_id);
+        self.reveal_deadlines.delete(game_id);
+        self.reveal_open_blocks.delete(game_id);
+        self.game_start_blocks.delete(game_id);
+        self.set_stage(game_id, Stage::FirstCommit);
+
+        // Send the refunds last, once the game is reset
+        let bet = self.bet.get(game_id);
+        for &player in &refunded {
+            self.pay(player, bet)?; // Refund the committed bet
+        }
+        if shared > U256::from(0) {
+            // Both players are seated while the game carries funds between rounds
+            let (share0, share1) = expiry_shares(shared, committed, deadline_passed);
+            for (player, share) in [(players[0], share0), (players[1], share1)] {
+                if share > U256::from(0) {
+                    self.pay(player, share)?;
+                }
+            }
+        }
+        evm::log(GameTimedOut { gameId: game_id, refunded });
+
+        Ok(())
+    }
+
+    // The `withdraw` function is used to claim the caller's internal balance: winnings, fees and refunds
+    // The pending balance is zeroed before the transfer, so it can't be claimed twice
+    pub fn withdraw(&mut self) -> Result<(), RpsError> {
+        self.non_reentrant()?;
+
+        let amount = self.pending_withdrawals.get(msg::sender());
+        if amount == U256::from(0) {
+            return Err(NothingToWithdraw {}.into()); // Return an error if there is nothing to claim
+        }
+
+        self.pending_withdrawals.insert(msg::sender(), U256::from(0)); // Zero the pending balance
+        self.release(amount);
+        evm::log(Withdrawal { player: msg::sender(), amount }); // Let wallets reconcile the balance history
+        self.guarded_transfer(msg::sender(), amount)?; // Transfer the pending balance to the caller, as the last step
+
+        Ok(())
+    }
+
+    // The `pending_of` function returns the internal balance of an address: its winnings, fees and refunds not yet withdrawn
+    // A non-zero balance can be claimed with `withdraw`
+    pub fn pending_of(&self, player: Address) -> Result<U256, RpsError> {
+        Ok(self.pending_withdrawals.get(player))
+    }
+
+    // The `version` function returns `VERSION`, so clients can tell which behaviour a deployment has
+    // It takes no `self`, so it is exported as a `pure` function
+    pub fn version() -> Result<U256, RpsError> {
+        Ok(U256::from(VERSION))
+    }
+
+    // The `get_stage` function returns the current stage of a game's round
+    // It takes `&self`, so it is exported as a `view` function in the ABI
+    // Stages:
+    //   0 = FirstCommit  (nobody has committed to the round yet; in a series either seated player may commit first)
+    //   1 = SecondCommit (one player has committed and the opponent is awaited)
+    //   2 = Distribute   (both players have committed: the players reveal, then `distribute` can be called, see `reveal_deadline`)
+    pub fn get_stage(&self, game_id: U256) -> Result<U256, RpsError> {
+        self.require_game(game_id)?;
+        Ok(self.stage.get(game_id))
+    }
+
+    // The `commit_deadline` function returns the last block the second player of a game may commit in
+    // It returns 0 unless a game is waiting for its second commit
+    pub fn commit_deadline(&self, game_id: U256) -> Result<U256, RpsError> {
+        self.require_game(game_id)?;
+
+        if self.game_stage(game_id)? != Stage::SecondCommit {
+            return Ok(U256::from(0));
+        }
+        Ok(self.commit_deadlines.get(game_id))
+    }
+
+    // The `next_actor` function returns the one address expected to move next in a game, or the zero address if anyone may act
+    //   FirstCommit:  zero, since either seated player (in a series) or anyone (in a new game) may commit first
+    //   SecondCommit: the seated player who hasn't committed in a series, or zero in a new game, which anyone else may join
+    //   Distribute:   the player who hasn't revealed once the other has, see `pending_revealer`; zero before either reveal,
+    //                 and once both have revealed or the reveal deadline has passed, since anyone may then call `distribute`
+    pub fn next_actor(&self, game_id: U256) -> Result<Address, RpsError> {
+        self.require_game(game_id)?;
+
+        let stage = self.game_stage(game_id)?;
+        if stage == Stage::Distribute {
+            if U256::from(block::number()) > self.reveal_deadlines.get(game_id) {
+                return Ok(Address::ZERO); // Nobody may reveal any more
+            }
+            let choices = self.player_choices.getter(game_id);
+            let revealed = [choices.get(U256::from(0)) != U256::from(0), choices.get(U256::from(1)) != U256::from(0)];
+            return Ok(pending_revealer(self.seated(game_id), revealed));
+        }
+
+        if stage != Stage::SecondCommit {
+            return Ok(Address::ZERO);
+        }
+
+        if !self.series_in_progress(game_id) {
+            return Ok(Address::ZERO); // Anyone but the first player may take the second slot
+        }
+
+        // Both players are seated, so the one without a commitment is up
+        let waiting = U256::from(1) - self.committed_slot(game_id);
+        Ok(self.player_addresses.getter(game_id).get(waiting))
+    }
+
+}
+
+// Internal helpers for the `RPS` contract, not exposed in the ABI
+impl RPS {
+    // Commit `player`'s `choice` to a game, paying `value` towards its bet
+    // `commit` passes the whole `msg::value()`, which must cover the bet
+    fn commit_to(&mut self, game_id: U256, player: Address, commitment: U256, value: U256) -> Result<(), RpsError> {
+        // A zero commitment would be indistinguishable from an empty slot
+        // The choice itself is only checked against the rules variant when it is revealed
+        if commitment == U256::from(0) {
+            return Err(InvalidCommitment {}.into());
+        }
+
+        if self.locked.get() {
+            return Err(ContractLocked {}.into()); // Return an error if the contract is locked
+        }
+
+        self.require_game(game_id)?;
+
+        let stage = self.game_stage(game_id)?; // Get the current stage
+        if stage == Stage::Distribute {
+            // Return an error until the round is distributed and the game is back to FirstCommit
+            return Err(InvalidStage { expected: Stage::FirstCommit.into(), actual: stage.into() }.into());
+        }
+
+        // Get the player index: the next free slot in a new series, or the caller's own slot in a running series
+        let player_index = if self.series_in_progress(game_id) {
+            let index = self.require_participant(game_id, player)?; // Return an error if the player isn't one of the seated players
+
+            if self.player_commitments.getter(game_id).get(index) != U256::from(0) {
+                return Err(AlreadyCommitted {}.into()); // Return an error if the player already committed
+            }
+            index
+        } else {
+            if stage == Stage::SecondCommit && player == self.player_addresses.getter(game_id).get(U256::from(0)) {
+                return Err(SelfPlay {}.into()); // Return an error if the first player tries to take the second slot too
+            }
+
+            stage.into() // The stage is the number of players seated so far, so it is the next free slot
+        };
+
+        // Outside a running series, a late second commit is refused so the first player's refund can't be raced
+        // Inside a series the round isn't reclaimable, so the opponent may still commit late
+        if stage == Stage::SecondCommit
+            && !self.series_in_progress(game_id)
+            && U256::from(block::number()) > self.commit_deadlines.get(game_id)
+        {
+            return Err(CommitWindowClosed {}.into()); // Return an error once the first player may call `reclaim_stalled`
+        }
+
+        // A game past its limit is waiting for `expire_game`, so it takes no more stakes
+        let start = self.game_start_blocks.get(game_id);
+        if start != U256::from(0) && U256::from(block::number()) > expiry_block(start, self.max_game_blocks.get()) {
+            return Err(GameTooOld {}.into()); // Return an error once anyone may expire the game
+        }
+
+        let excess = self.take_bet(game_id, value)?;
+        self.start_game_clock(game_id);
+
+        self.player_commitments.setter(game_id).insert(player_index, commitment); // Store the player's commitment
+        self.player_addresses.setter(game_id).insert(player_index, player); // Store the player's address
+
+        // Announce the commit without the commitment itself, which only matters to `reveal`
+        evm::log(Committed { gameId: game_id, player, playerIndex: player_index });
+
+        if stage == Stage::FirstCommit {
+            // Start the clock for the opponent's commit; a span too large to add simply never expires
+            let deadline = U256::from(block::number()).saturating_add(self.commit_span.get());
+            self.commit_deadlines.insert(game_id, deadline);
+        }
+
+        // Advance the stage to the next player, or start the reveals once both players have committed
+        if stage == Stage::FirstCommit {
+            self.set_stage(game_id, Stage::SecondCommit);
+        } else {
+            self.start_reveals(game_id);
+        }
+
+        #[cfg(feature = "debug")]
+        self.assert_escrowed(game_id);
+
+        self.refund_excess(excess)
+    }
+
+    // Record the block of a game's first commit, which `expire_game` measures the game's duration from
+    // Later commits to the same game, including the rounds of a series, keep the original start
+    fn start_game_clock(&mut self, game_id: U256) {
+        if self.game_start_blocks.get(game_id) == U256::from(0) {
+            self.game_start_blocks.insert(game_id, U256::from(block::number()));
+        }
+    }
+
+    // Move a game whose seats are all committed to the Distribute stage and open its reveal window
+    // The window lasts `commit_span` blocks, like the second player's commit window; a span too large to add never expires
+    fn start_reveals(&mut self, game_id: U256) {
+        let opens = reveal_opens_at(block::number(), U256::from(REVEAL_GAP));
+        self.reveal_open_blocks.insert(game_id, opens);
+
+        let deadline = opens.saturating_add(self.commit_span.get()); // The full span is left for revealing once the window opens
+        self.reveal_deadlines.insert(game_id, deadline);
+        self.set_stage(game_id, Stage::Distribute);
+    }
+
+    // Whether every seat of a game has revealed its choice
+    fn all_revealed(&self, game_id: U256) -> bool {
+        let choices = self.player_choices.getter(game_id);
+        [U256::from(0), U256::from(1)].into_iter().all(|index| choices.get(index) != U256::from(0))
+    }
+
+    // Check the value paid towards a game's bet, returning the excess to refund to the caller
+    // The refund is left to the caller, to be sent once the commit is stored, so no state changes after the transfer
+    fn take_bet(&mut self, game_id: U256, value: U256) -> Result<U256, RpsError> {
+        let bet = self.bet.get(game_id);
+        if bet == U256::from(0) {
+            return Err(ZeroBet {}.into()); // Return an error rather than accept a free commit
+        }
+
+        if self.strict_bet.get() && value != bet {
+            return Err(ExactBetRequired {}.into()); // Return an error if strict accounting is on and the value isn't the bet
+        }
+
+        if value < bet {
+            return Err(InsufficientFunds {}.into()); // Return an error if the committed funds are insufficient
+        }
+
+        Ok(value - bet) // The player may have sent more than the required bet amount
+    }
+
+    // Refund the excess of a commit's value to the caller, as the last step of the commit
+    fn refund_excess(&mut self, excess: U256) -> Result<(), RpsError> {
+        if excess > U256::from(0) {
+            self.pay(msg::sender(), excess)?;
+        }
+        Ok(())
+    }
+
+    // Return an error if called while the contract is sending ETH, i.e. from a recipient re-entering
+    fn non_reentrant(&self) -> Result<(), RpsError> {
+        if self.in_call.get() {
+            return Err(ReentrantCall {}.into());
+        }
+        Ok(())
+    }
+
+    // Send ETH with the reentrancy guard held, so the recipient can't re-enter a guarded function
+    // The guard flag is the only storage written after the call, to release the guard
+    fn guarded_transfer(&mut self, to: Address, amount: U256) -> Result<(), RpsError> {
+        self.in_call.set(true);
+        let result = call::transfer_eth(to, amount);
+        self.in_call.set(false);
+        result.map_err(|_| TransferFailed { to, amount }.into())
+    }
+
+    // Send `amount` to `to`, or credit it for later withdrawal if the transfer fails
+    // Refunds go through here, so a recipient that rejects ETH can never block a game
+    // The amount is released from the escrow before the transfer, see `payment_escrow`, so after a successful call
+    // only the guard flag is restored; the escrow and the credit are only written back after a failed one
+    fn pay(&mut self, to: Address, amount: U256) -> Result<(), RpsError> {
+        let (released, restored) = payment_escrow(self.total_escrowed.get(), amount);
+        self.total_escrowed.set(released);
+        if self.guarded_transfer(to, amount).is_err() {
+            self.total_escrowed.set(restored); // Still escrowed, now as the recipient's internal balance
+            self.credit(to, amount)?;
+            evm::log(PaymentDeferred { to, amount }); // Tell the recipient to claim it with `withdraw`
+        }
+        Ok(())
+    }
+
+    // Count the ETH sent to a payable function as escrowed; called once per call, before it is staked or credited
+    fn escrow_value(&mut self) -> Result<(), RpsError> {
+        self.escrow(msg::value())
+    }
+
+    // Count `amount` as escrowed
+    fn escrow(&mut self, amount: U256) -> Result<(), RpsError> {
+        let escrowed = self.total_escrowed.get().checked_add(amount).ok_or(BalanceOverflow {})?;
+        self.total_escrowed.set(escrowed);
+        Ok(())
+    }
+
+    // Stop counting ETH that was sent out of the contract as escrowed
+    // Saturates, so an accounting slip can only make `sweep_unaccounted` sweep less, never block a payment
+    fn release(&mut self, amount: U256) {
+        self.total_escrowed.set(self.total_escrowed.get().saturating_sub(amount));
+    }
+
+    // Add `amount` to the internal balance of `to`, which `withdraw` pays out
+    // The addition is checked, so the credited total can never wrap around and exceed the contract's balance
+    fn credit(&mut self, to: Address, amount: U256) -> Result<(), RpsError> {
+        let pending = self.pending_withdrawals.get(to).checked_add(amount).ok_or(BalanceOverflow {})?;
+        self.pending_withdrawals.insert(to, pending);
+        Ok(())
+    }
+
+    // Whether a choice can be played under the contract's rules variant
+    fn playable(&self, choice: U256) -> bool {
+        Choice::try_from(choice).is_ok_and(|choice| allowed_choice(choice, self.variant.get() == U8::from(1)))
+    }
+
+    // Return an error once the emergency is armed, for the functions that would change or lift the contract's lock
+    fn check_lock_change(&self) -> Result<(), RpsError> {
+        check_lock_change(self.emergency_unlock_block.get())
+    }
+
+    // Whether `arm_emergency` has been called
+    fn emergency_armed(&self) -> bool {
+        self.emergency_unlock_block.get() != U256::from(0)
+    }
+
+    // Return an error unless the caller is the owner
+    fn only_owner(&self) -> Result<(), RpsError> {
+        if msg::sender() != self.owner.get() {
+            return Err(NotOwner {}.into());
+        }
+        Ok(())
+    }
+
+    // Read a game's stage
+    fn game_stage(&self, game_id: U256) -> Result<Stage, RpsError> {
+        Stage::try_from(self.stage.get(game_id))
+    }
+
+    // Return an error carrying both stages unless a game is in the `expected` stage
+    fn require_stage(&self, game_id: U256, expected: Stage) -> Result<(), RpsError> {
+        let actual = self.game_stage(game_id)?;
+        if actual != expected {
+            return Err(InvalidStage { expected: expected.into(), actual: actual.into() }.into());
+        }
+        Ok(())
+    }
+
+    // Move a game to another stage, logging the transition
+    // Every stage write goes through here, so the `StageChanged` events are a complete trace of each game's state machine
+    // Staying in the same stage is neither written nor logged, e.g. when `create_game` sets a new game's FirstCommit stage,
+    // which an unused game id already reads as
+    fn set_stage(&mut self, game_id: U256, stage: Stage) {
+        let old_stage = self.stage.get(game_id);
+        let new_stage: U256 = stage.into();
+        if old_stage == new_stage {
+            return;
+        }
+        self.stage.insert(game_id, new_stage);
+        evm::log(StageChanged { gameId: game_id, oldStage: old_stage, newStage: new_stage });
+    }
+
+    // The addresses seated in a game's two slots, the zero address for an empty one
+    fn seated(&self, game_id: U256) -> [Address; 2] {
+        let players = self.player_addresses.getter(game_id);
+        [players.get(U256::from(0)), players.get(U256::from(1))]
+    }
+
+    // Return the slot `player` is seated in, or an error if they aren't seated in the game
+    // Every check that a caller plays in a game goes through here
+    fn require_participant(&self, game_id: U256, player: Address) -> Result<U256, RpsError> {
+        seat_of(self.seated(game_id), player).ok_or(NotAPlayer {}.into())
+    }
+
+    // Return an error unless `game_id` was returned by `create_game`
+    fn require_game(&self, game_id: U256) -> Result<(), RpsError> {
+        if self.creators.get(game_id) == Address::ZERO {
+            return Err(UnknownGame {}.into());
+        }
+        Ok(())
+    }
+
+    // Settle a decided series: reset the pot, scores and players, then pay out the pot
+    fn settle_series(&mut self, game_id: U256, winner_address: Address, pot: U256) -> Result<(), RpsError> {
+        // Reset the pot, scores and players for a new series
+        self.pot.delete(game_id);
+        self.scores.setter(game_id).delete(U256::from(0));
+        self.scores.setter(game_id).delete(U256::from(1));
+        self.clear_players(game_id);
+
+        self.pay_out(game_id, winner_address, pot)
+    }
+
+    // Take the house fee from a decided game's pot and credit the rest to the winner
+    fn pay_out(&mut self, game_id: U256, winner_address: Address, pot: U256) -> Result<(), RpsError> {
+        // Take the house fee, rounded down so the winner is never short-changed by rounding
+        let fee = pot * U256::from(self.fee_bps.get()) / U256::from(10000);
+        let payout = pot - fee;
+        if fee > U256::from(0) {
+            let owner = self.owner.get();
+            self.credit(owner, fee)?; // Credit the fee to the owner
+            evm::log(FeeCollected { owner, amount: fee }); // Announce the collected fee
+        }
+
+        // Credit the winnings instead of pushing them, so a winner that rejects ETH can't block the payout
+        self.credit(winner_address, payout)?;
+        evm::log(Winner { gameId: game_id, winner: winner_address, amount: payout }); // Announce the outcome
+
+        Ok(())
+    }
+
+    // Panic unless the contract holds at least what a game has escrowed: its series pot plus one bet per committed player
+    // This only catches accounting bugs within a single game, so it is compiled in with the `debug` feature only
+    #[cfg(feature = "debug")]
+    fn assert_escrowed(&self, game_id: U256) {
+        let expected = self.pot.get(game_id) + self.bet.get(game_id) * self.stage.get(game_id);
+        assert!(contract::balance() >= expected, "contract holds less than game {} has escrowed", game_id);
+    }
+
+    // Clear both choices and commitments of a game, and its reveal window, so the next round starts fresh
+    fn clear_choices(&mut self, game_id: U256) {
+        let mut choices = self.player_choices.setter(game_id);
+        choices.delete(U256::from(0));
+        choices.delete(U256::from(1));
+        let mut commitments = self.player_commitments.setter(game_id);
+        commitments.delete(U256::from(0));
+        commitments.delete(U256::from(1));
+        self.reveal_deadlines.delete(game_id);
+        self.reveal_open_blocks.delete(game_id);
+    }
+
+    // Clear both player slots of a game, so a new game can't inherit the previous game's players, and stop the game's clock
+    fn clear_players(&mut self, game_id: U256) {
+        let mut players = self.player_addresses.setter(game_id);
+        players.delete(U256::from(0));
+        players.delete(U256::from(1));
+        self.game_start_blocks.delete(game_id);
+    }
+
+    // Return the slot holding the only commitment of a game in the SecondCommit stage
+    // In a new game this is always slot 0, but in a running series either player may commit first
+    fn committed_slot(&self, game_id: U256) -> U256 {
+        if self.player_commitments.getter(game_id).get(U256::from(0)) != U256::from(0) {
+            U256::from(0)
+        } else {
+            U256::from(1)
+        }
+    }
+
+    // A series is in progress once one of its rounds has been decided
+    // From then on, only the two series players may commit until the series is won
+    fn series_in_progress(&self, game_id: U256) -> bool {
+        let scores = self.scores.getter(game_id);
+        scores.get(U256::from(0)) > U256::from(0) || scores.get(U256::from(1)) > U256::from(0)
+    }
+}
+
+// Unit tests for the pure game logic
+// These run on the host with `cargo test` and don't touch contract storage
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bet_bounds() {
+        let (min_bet, max_bet) = (U256::from(10), U256::from(100));
+
+        // Both bounds are inclusive
+        assert!(check_bet(min_bet, min_bet, max_bet).is_ok());
+        assert!(check_bet(max_bet, min_bet, max_bet).is_ok());
+        assert!(check_bet(U256::from(50), min_bet, max_bet).is_ok());
+
+        // One wei outside either bound is rejected
+        assert!(check_bet(min_bet - U256::from(1), min_bet, max_bet).is_err());
+        assert!(check_bet(max_bet + U256::from(1), min_bet, max_bet).is_err());
+
+        // A zero bet is rejected as soon as the minimum is above zero
+        assert!(check_bet(U256::from(0), min_bet, max_bet).is_err());
+        assert!(check_bet(U256::from(0), U256::from(1), max_bet).is_err());
+
+        // A zero bet is rejected even if the bounds would allow it
+        assert!(check_bet(U256::from(0), U256::from(0), max_bet).is_err());
+    }
+
+    #[test]
+    fn commitment_preimage() {
+        let blinding_factor = U256::from(0x1234_5678u64);
+        let sender = Address::repeat_byte(0xab);
+        let commitment = commitment::compute_commitment(1, blinding_factor, sender);
+
+        // The preimage is Solidity's abi.encodePacked(uint256, uint256, address)
+        use alloy_sol_types::SolValue;
+        let preimage = (U256::from(1), blinding_factor, sender).abi_encode_packed();
+        assert_eq!(preimage, commitment::commitment_preimage(1, blinding_factor, sender));
+        assert_eq!(commitment, U256::from_be_bytes(alloy_primitives::keccak256(&preimage).0));
+
+        // Changing any part of the preimage changes the commitment
+        assert_ne!(commitment, commitment::compute_commitment(2, blinding_factor, sender));
+        assert_ne!(commitment, commitment::compute_commitment(1, blinding_factor + U256::from(1), sender));
+        assert_ne!(commitment, commitment::compute_commitment(1, blinding_factor, Address::repeat_byte(0xcd)));
+    }
+
+    #[test]
+    fn game_ids() {
+        let (alice, bob) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+
+        // The id is abi.encodePacked(address, uint256) hashed, as a client would compute it
+        use alloy_sol_types::SolValue;
+        let packed = (alice, U256::from(3)).abi_encode_packed();
+        assert_eq!(derive_game_id(alice, U256::from(3)), U256::from_be_bytes(alloy_primitives::keccak256(&packed).0));
+
+        // Every nonce and every creator gives a different id
+        assert_ne!(derive_game_id(alice, U256::from(0)), derive_game_id(alice, U256::from(1)));
+        assert_ne!(derive_game_id(alice, U256::from(0)), derive_game_id(bob, U256::from(0)));
+    }
+
+    // Revealing the committed preimage must always succeed, and changing any one of its fields must always fail
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(4096))]
+
+        #[test]
+        fn commitment_binds_preimage(
+            choice in 1u8..=5,
+            blinding_factor in proptest::prelude::any::<[u8; 32]>(),
+            sender in proptest::prelude::any::<[u8; 20]>(),
+            other_choice in 0u8..=5,
+            other_blinding in proptest::prelude::any::<[u8; 32]>(),
+            other_sender in proptest::prelude::any::<[u8; 20]>(),
+        ) {
+            let (blinding_factor, sender) = (U256::from_be_bytes(blinding_factor), Address::from(sender));
+            let commitment = commitment::compute_commitment(choice, blinding_factor, sender);
+            proptest::prop_assert!(commitment::verify_commitment(commitment, choice, blinding_factor, sender));
+
+            let (other_blinding, other_sender) = (U256::from_be_bytes(other_blinding), Address::from(other_sender));
+            if other_choice != choice {
+                proptest::prop_assert!(!commitment::verify_commitment(commitment, other_choice, blinding_factor, sender));
+            }
+            if other_blinding != blinding_factor {
+                proptest::prop_assert!(!commitment::verify_commitment(commitment, choice, other_blinding, sender));
+            }
+            if other_sender != sender {
+                proptest::prop_assert!(!commitment::verify_commitment(commitment, choice, blinding_factor, other_sender));
+            }
+        }
+    }
+
+    // Read-only functions must be exported as `view` or `pure`, so wallets and tools use `eth_call` for them
+    #[cfg(feature = "export-abi")]
+    #[test]
+    fn abi_marks_read_only_functions() {
+        let abi = ExportedAbi.to_string();
+
+        for view in [
+            "function owner() external view",
+            "function getStage(uint256 game_id) external view",
+            "function pendingOf(address player) external view",
+            "function commitDeadline(uint256 game_id) external view",
+            "function revealDeadline(uint256 game_id) external view",
+            "function nextActor(uint256 game_id) external view",
+        ] {
+            assert!(abi.contains(view), "missing `{view}` in:\n{abi}");
+        }
+        assert!(abi.contains("function version() external pure"), "{abi}");
+
+        // Balance changes are logged, so wallets can reconcile them
+        assert!(abi.contains("event Withdrawal(address indexed player, uint256 amount);"), "{abi}");
+
+        // Every event is declared on a line of its own, however long its declaration is
+        let lines: Vec<&str> = abi.lines().collect();
+        let line = "    event GameTimedOut(uint256 indexed gameId, address[] refunded);";
+        assert!(lines.contains(&line), "missing the line `{line}` in:\n{abi}");
+        let events: Vec<&&str> = lines.iter().filter(|line| line.trim_start().starts_with("event ")).collect();
+        assert_eq!(events.len(), EVENT_DECLARATIONS.len(), "{abi}");
+        assert!(events.iter().all(|line| line.ends_with(");")), "{abi}");
+
+
+        // State-changing functions must not be marked read-only
+        assert!(abi.contains("function commit(uint256 game_id, uint256 commitment) external payable;"), "{abi}");
+        assert!(abi.contains("function reveal(uint256 game_id, uint256 choice, uint256 blinding_factor) external;"), "{abi}");
+        assert!(abi.contains("function distribute(uint256 game_id) external returns (uint256);"), "{abi}");
+    }
+
+    // The host client must call the selectors the contract actually exports, which are camelCase
+    #[cfg(all(feature = "client", feature = "export-abi"))]
+    #[test]
+    fn client_matches_exported_abi() {
+        // Reduce each exported declaration to its canonical signature, e.g. `createGame(uint256)`
+        let abi = ExportedAbi.to_string();
+        let exported: Vec<String> = abi
+            .split(';')
+            .map(|chunk| chunk.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter_map(|chunk| {
+                let start = chunk.find("function ").map(|at| at + "function ".len()).or_else(|| chunk.find("event ").map(|at| at + "event ".len()))?;
+                Some(chunk[start..].to_string())
+            })
+            .map(|declaration| {
+                let (name, rest) = declaration.split_once('(').unwrap();
+                let params = &rest[..rest.find(')').unwrap()];
+                let types: Vec<&str> = params.split(',').filter_map(|param| param.split_whitespace().next()).collect();
+                format!("{name}({})", types.join(","))
+            })
+            .collect();
+
+        let binding = &*client::RPSBINDING_ABI;
+        let functions = binding.functions().map(|f| (&f.name, f.inputs.iter().map(|p| p.kind.to_string()).collect::<Vec<_>>()));
+        let events = binding.events().map(|e| (&e.name, e.inputs.iter().map(|p| p.kind.to_string()).collect::<Vec<_>>()));
+        for (name, types) in functions.chain(events) {
+            let signature = format!("{name}({})", types.join(","));
+            assert!(exported.contains(&signature), "the client uses `{signature}`, which isn't exported in:\n{abi}");
+        }
+    }
+
+    #[test]
+    fn version_matches_crate() {
+        let part = |s: &str| s.parse::<u64>().unwrap();
+        let expected = part(env!("CARGO_PKG_VERSION_MAJOR")) * 10000
+            + part(env!("CARGO_PKG_VERSION_MINOR")) * 100
+            + part(env!("CARGO_PKG_VERSION_PATCH"));
+        assert_eq!(VERSION, expected, "bump VERSION together with the crate version");
+    }
+
+    #[test]
+    fn round_winnings_overflow() {
+        let half = U256::MAX / U256::from(2);
+        assert!(round_winnings(U256::from(5)).ok() == Some(U256::from(10)));
+        assert!(round_winnings(half).ok() == Some(U256::MAX - U256::from(1)));
+
+        // One wei more and doubling no longer fits, so the payout is refused rather than wrapped
+        assert!(round_winnings(half + U256::from(1)).is_err());
+        assert!(round_winnings(U256::MAX).is_err());
+    }
+
+    #[test]
+    fn expiry_splits() {
+        let shared = U256::from(1001);
+
+        // While both or neither of the players committed to the stalled round, each gets their own stakes back
+        for deadline_passed in [false, true] {
+            assert_eq!(expiry_shares(shared, [true, true], deadline_passed), (U256::from(500), U256::from(501)));
+            assert_eq!(expiry_shares(shared, [false, false], deadline_passed), (U256::from(500), U256::from(501)));
+        }
+
+        // A player who let their commit deadline pass while the other committed forfeits their half, whatever the score
+        assert_eq!(expiry_shares(shared, [true, false], true), (shared, U256::from(0)));
+        assert_eq!(expiry_shares(shared, [false, true], true), (U256::from(0), shared));
+
+        // Within the deadline the opponent hasn't stalled yet, so the pot is still split evenly
+        assert_eq!(expiry_shares(shared, [true, false], false), (U256::from(500), U256::from(501)));
+        assert_eq!(expiry_shares(shared, [false, true], false), (U256::from(500), U256::from(501)));
+    }
+
+    #[test]
+    fn commit_then_expire() {
+        // A series round opens at block 1_000 with one commit, in a game that started at block 100
+        let (start, max_game_blocks, commit_span) = (U256::from(100), U256::from(1_000), U256::from(50));
+        let limit = expiry_block(start, max_game_blocks);
+        let commit_deadline = U256::from(1_000) + commit_span;
+
+        // Up to the limit the opponent may still commit and nobody may expire the game
+        assert!(limit == U256::from(1_100));
+        assert!(!wait_over(limit, limit, false));
+
+        // Past the limit commits are refused, the game can be expired, and the opponent who let
+        // their deadline pass forfeits their half of the pot
+        let block = limit + U256::from(1);
+        assert!(block > limit && wait_over(limit, block, false));
+        assert_eq!(expiry_shares(U256::from(400), [true, false], block > commit_deadline), (U256::from(400), U256::from(0)));
+
+        // Lowered by the owner, the limit can fall inside the opponent's commit window, who then keeps their half
+        let limit = expiry_block(start, U256::from(920));
+        let block = limit + U256::from(1);
+        assert!(wait_over(limit, block, false) && block <= commit_deadline);
+        assert_eq!(expiry_shares(U256::from(400), [true, false], block > commit_deadline), (U256::from(200), U256::from(200)));
+
+        // An enormous limit never lets the game expire
+        assert!(expiry_block(start, U256::MAX) == U256::MAX);
+    }
+
+    #[test]
+    fn payment_escrow_order() {
+        // The amount leaves the escrow before the transfer, and a failed transfer puts exactly that amount back
+        let (released, restored) = payment_escrow(U256::from(100), U256::from(30));
+        assert!(released == U256::from(70));
+        assert!(restored == U256::from(100));
+
+        // Paying out the whole escrow leaves nothing counted during the call
+        assert!(payment_escrow(U256::from(100), U256::from(100)) == (U256::from(0), U256::from(100)));
+
+        // An accounting slip saturates instead of reverting, and a failed payment then counts as escrowed in full
+        assert!(payment_escrow(U256::from(10), U256::from(30)) == (U256::from(0), U256::from(30)));
+    }
+
+    #[test]
+    fn unaccounted_balance() {
+        assert!(unaccounted(U256::from(150), U256::from(100)) == U256::from(50));
+        assert!(unaccounted(U256::from(100), U256::from(100)) == U256::from(0));
+
+        // A balance short of the escrow leaves nothing to sweep
+        assert!(unaccounted(U256::from(90), U256::from(100)) == U256::from(0));
+    }
+
+    #[test]
+    fn invalid_stored_choice_forfeits() {
+        // Valid stored values read back unchanged
+        for value in 0..=5u64 {
+            let read: U256 = stored_choice(U256::from(value)).into();
+            assert!(read == U256::from(value));
+        }
+
+        // Anything else reads as None, which loses to a real choice instead of reverting `distribute`
+        for value in [U256::from(6), U256::from(256), U256::MAX] {
+            assert!(stored_choice(value) == Choice::None);
+            assert_eq!(decide_winner(stored_choice(value), Choice::Rock), WinResult::PlayerOne);
+            assert_eq!(decide_winner(Choice::Paper, stored_choice(value)), WinResult::PlayerZero);
+        }
+    }
+
+    #[test]
+    fn reveal_gap() {
+        // With the default gap the reveal window opens in the block after the last commit
+        let opens = reveal_opens_at(1_000, U256::from(REVEAL_GAP));
+        assert!(opens == U256::from(1_001));
+        assert!(U256::from(1_000) < opens); // A reveal in the commit's own block is too early
+
+        // A zero gap allows revealing in the same block, and an enormous one saturates
+        assert!(reveal_opens_at(1_000, U256::from(0)) == U256::from(1_000));
+        assert!(reveal_opens_at(1_000, U256::MAX) == U256::MAX);
+    }
+
+    #[test]
+    fn emergency_lock_is_final() {
+        // Before the emergency is armed, the owner may lock, unlock and arm it
+        assert!(check_lock_change(U256::from(0)).is_ok());
+
+        // Once armed, nothing can lift, replace or re-arm the lock, before or after the sweep
+        assert!(check_lock_change(U256::from(1_000 + EMERGENCY_DELAY)).is_err());
+
+        // Refund paths wait for their deadline, unless the emergency is armed, when they run at once
+        let deadline = U256::from(1_000 + DEFAULT_MAX_GAME_BLOCKS);
+        assert!(!wait_over(deadline, U256::from(1_000), false));
+        assert!(!wait_over(deadline, deadline, false));
+        assert!(wait_over(deadline, deadline + U256::from(1), false));
+        assert!(wait_over(deadline, U256::from(1_000), true));
+    }
+
+    #[test]
+    fn allowed_choices() {
+        for choice in [Choice::Rock, Choice::Paper, Choice::Scissors] {
+            assert!(allowed_choice(choice, false));
+            assert!(allowed_choice(choice, true));
+        }
+        for choice in [Choice::Lizard, Choice::Spock] {
+            assert!(!allowed_choice(choice, false));
+            assert!(allowed_choice(choice, true));
+        }
+        assert!(!allowed_choice(Choice::None, true));
+    }
+
+    #[test]
+    fn participant_seats() {
+        let (player0, player1, outsider) = (Address::repeat_byte(0x11), Address::repeat_byte(0x22), Address::repeat_byte(0x33));
+        let seated = [player0, player1];
+        assert_eq!(seat_of(seated, player0), Some(U256::from(0)));
+        assert_eq!(seat_of(seated, player1), Some(U256::from(1)));
+
+        // A non-participant is rejected by `reveal` and commits into a running series alike
+        assert_eq!(seat_of(seated, outsider), None);
+
+        // An empty slot doesn't seat the zero address
+        assert_eq!(seat_of([player0, Address::ZERO], Address::ZERO), None);
+
+        // A game with a single committer can't be revealed into
+        assert!(all_seated(seated));
+        assert!(!all_seated([player0, Address::ZERO]));
+        assert!(!all_seated([Address::ZERO, player1]));
+    }
+
+    #[test]
+    fn next_revealer() {
+        let (player0, player1) = (Address::repeat_byte(0x11), Address::repeat_byte(0x22));
+        let seated = [player0, player1];
+
+        // Once one player has revealed, the other is up
+        assert_eq!(pending_revealer(seated, [true, false]), player1);
+        assert_eq!(pending_revealer(seated, [false, true]), player0);
+
+        // Before either reveal nobody in particular is up, nor once both have revealed
+        assert_eq!(pending_revealer(seated, [false, false]), Address::ZERO);
+        assert_eq!(pending_revealer(seated, [true, true]), Address::ZERO);
+    }
+
+    #[test]
+    fn stage_round_trip() {
+        for stage in [Stage::FirstCommit, Stage::SecondCommit, Stage::Distribute] {
+            let value: U256 = stage.into();
+            assert!(Stage::try_from(value).ok() == Some(stage));
+        }
+        assert!(Stage::try_from(U256::from(3)).is_err());
+    }
+
+    #[test]
+    fn none_matrix() {
+        // A player who didn't choose loses to any real choice, from either slot
+        for choice in [Choice::Rock, Choice::Paper, Choice::Scissors, Choice::Lizard, Choice::Spock] {
+            assert_eq!(decide_winner(Choice::None, choice), WinResult::PlayerOne);
+            assert_eq!(decide_winner(choice, Choice::None), WinResult::PlayerZero);
+        }
+
+        // If neither player chose, the round is void and refunded like a draw
+        assert_eq!(decide_winner(Choice::None, Choice::None), WinResult::Draw);
+    }
+
+    #[test]
+    fn classic_matrix() {
+        // Every (player 0, player 1) combination of Rock, Paper and Scissors
+        let cases = [
+            (Choice::Rock, Choice::Rock, WinResult::Draw),
+            (Choice::Rock, Choice::Paper, WinResult::PlayerOne),
+            (Choice::Rock, Choice::Scissors, WinResult::PlayerZero),
+            (Choice::Paper, Choice::Rock, WinResult::PlayerZero),
+            (Choice::Paper, Choice::Paper, WinResult::Draw),
+            (Choice::Paper, Choice::Scissors, WinResult::PlayerOne),
+            (Choice::Scissors, Choice::Rock, WinResult::PlayerOne),
+            (Choice::Scissors, Choice::Paper, WinResult::PlayerZero),
+            (Choice::Scissors, Choice::Scissors, WinResult::Draw),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(decide_winner(a, b), expected);
+        }
+    }
+
+    #[test]
+    fn extended_matrix() {
+        // Every gesture beats exactly two others, loses to two others, and draws against itself
+        let gestures = [Choice::Rock, Choice::Paper, Choice::Scissors, Choice::Lizard, Choice::Spock];
+        for a in gestures {
+            let wins = gestures.iter().filter(|&&b| decide_winner(a, b) == WinResult::PlayerZero).count();
+            let losses = gestures.iter().filter(|&&b| decide_winner(a, b) == WinResult::PlayerOne).count();
+            assert_eq!((wins, losses), (2, 2));
+            assert_eq!(decide_winner(a, a), WinResult::Draw);
+            for b in gestures {
+                // Swapping the players swaps the winner
+                let swapped = match decide_winner(b, a) {
+                    WinResult::PlayerZero => WinResult::PlayerOne,
+                    WinResult::PlayerOne => WinResult::PlayerZero,
+                    WinResult::Draw => WinResult::Draw,
+                };
+                assert_eq!(decide_winner(a, b), swapped);
+            }
+        }
+
+        assert_eq!(decide_winner(Choice::Rock, Choice::Lizard), WinResult::PlayerZero);
+        assert_eq!(decide_winner(Choice::Lizard, Choice::Spock), WinResult::PlayerZero);
+        assert_eq!(decide_winner(Choice::Spock, Choice::Scissors), WinResult::PlayerZero);
+        assert_eq!(decide_winner(Choice::Scissors, Choice::Lizard), WinResult::PlayerZero);
+        assert_eq!(decide_winner(Choice::Lizard, Choice::Paper), WinResult::PlayerZero);
+        assert_eq!(decide_winner(Choice::Paper, Choice::Spock), WinResult::PlayerZero);
+        assert_eq!(decide_winner(Choice::Spock, Choice::Rock), WinResult::PlayerZero);
+    }
 }
@@ -1,6 +1,15 @@
 #![cfg_attr(not(feature = "export-abi"), no_main)]
 
+// Print the interface with its events, which `rps_game::print_abi` would leave out
 #[cfg(feature = "export-abi")]
 fn main() {
-    rps - game::print_abi("MIT-OR-APACHE-2.0", "pragma solidity ^0.8.23;");
+    println!("/**");
+    println!(" * This file was automatically generated by Stylus and represents a Rust program.");
+    println!(" * For more information, please see [The Stylus SDK](https://github.com/OffchainLabs/stylus-sdk-rs).");
+    println!(" */");
+    println!();
+    println!("// SPDX-License-Identifier: MIT-OR-APACHE-2.0");
+    println!("pragma solidity ^0.8.23;");
+    println!();
+    print!("{}", rps_game::ExportedAbi);
 }